@@ -4,7 +4,7 @@ fn get_env(key: &str) -> MaaResult<String> {
     std::env::var(key).map_err(Into::into)
 }
 
-/**
+/*
  * log 相关
  */
 
@@ -16,7 +16,7 @@ pub fn log_prefix() -> MaaResult<String> {
     get_env("LOG_PREFIX")
 }
 
-/**
+/*
  * 数据库相关
  */
 
@@ -35,18 +35,126 @@ pub fn max_login_count() -> MaaResult<usize> {
         .and_then(|x| x.map_err(Into::into))
 }
 
-// JWT key
+// 注册方式, vcode: 注册前需要邮箱验证码, activation: 注册后通过邮件激活账户
+pub fn registration_mode() -> MaaResult<String> {
+    get_env("REGISTRATION_MODE")
+}
+
+// 启动时提升为管理员的账户邮箱, 用于在新数据库中创建第一个管理员
+pub fn admin_email() -> MaaResult<String> {
+    get_env("ADMIN_EMAIL")
+}
+
+// JWT 签名算法, 支持 HS256、ES256、RS256
+pub fn jwt_algorithm() -> MaaResult<String> {
+    get_env("JWT_ALGORITHM")
+}
+
+// JWT key, 使用 HS256 时的密钥
 pub fn jwt_key() -> MaaResult<String> {
     get_env("JWT_KEY")
 }
 
-// JWT 过期时间
+// JWT key id, 写入 token header 中的 kid
+pub fn jwt_key_id() -> MaaResult<String> {
+    get_env("JWT_KEY_ID")
+}
+
+// 使用 ES256/RS256 时的 PEM 私钥文件
+pub fn jwt_private_key_file() -> MaaResult<String> {
+    get_env("JWT_PRIVATE_KEY_FILE")
+}
+
+// 使用 ES256/RS256 时的 PEM 公钥文件
+pub fn jwt_public_key_file() -> MaaResult<String> {
+    get_env("JWT_PUBLIC_KEY_FILE")
+}
+
+// 密钥轮换期间仍然接受的旧密钥, 格式为 `kid=值,kid=值`,
+// HS256 时值为密钥, ES256/RS256 时值为 PEM 公钥文件路径
+pub fn jwt_previous_keys() -> MaaResult<String> {
+    get_env("JWT_PREVIOUS_KEYS")
+}
+
+// JWT 过期时间, 未单独配置 auth token 过期时间时使用
 pub fn jwt_expire_time() -> MaaResult<u64> {
     get_env("JWT_EXPIRE_TIME")
         .map(|x| x.parse())
         .and_then(|x| x.map_err(Into::into))
 }
 
+// auth token 过期时间
+pub fn jwt_auth_expire_time() -> MaaResult<u64> {
+    get_env("JWT_AUTH_EXPIRE_TIME")
+        .map(|x| x.parse())
+        .and_then(|x| x.map_err(Into::into))
+}
+
+// refresh token 过期时间
+pub fn jwt_refresh_expire_time() -> MaaResult<u64> {
+    get_env("JWT_REFRESH_EXPIRE_TIME")
+        .map(|x| x.parse())
+        .and_then(|x| x.map_err(Into::into))
+}
+
+// 登录时勾选"记住我"后的 refresh token 过期时间
+pub fn jwt_refresh_remember_expire_time() -> MaaResult<u64> {
+    get_env("JWT_REFRESH_REMEMBER_EXPIRE_TIME")
+        .map(|x| x.parse())
+        .and_then(|x| x.map_err(Into::into))
+}
+
+// 新密码使用的哈希算法, 支持 bcrypt、argon2id
+pub fn password_hash_algorithm() -> MaaResult<String> {
+    get_env("PASSWORD_HASH_ALGORITHM")
+}
+
+pub fn bcrypt_cost() -> MaaResult<u32> {
+    get_env("BCRYPT_COST")
+        .map(|x| x.parse())
+        .and_then(|x| x.map_err(Into::into))
+}
+
+// argon2id 内存开销(KiB)
+pub fn argon2_memory_cost() -> MaaResult<u32> {
+    get_env("ARGON2_MEMORY_COST")
+        .map(|x| x.parse())
+        .and_then(|x| x.map_err(Into::into))
+}
+
+// argon2id 迭代次数
+pub fn argon2_time_cost() -> MaaResult<u32> {
+    get_env("ARGON2_TIME_COST")
+        .map(|x| x.parse())
+        .and_then(|x| x.map_err(Into::into))
+}
+
+pub fn argon2_parallelism() -> MaaResult<u32> {
+    get_env("ARGON2_PARALLELISM")
+        .map(|x| x.parse())
+        .and_then(|x| x.map_err(Into::into))
+}
+
+// 同时执行的密码哈希任务数, 默认为 CPU 核数
+pub fn password_hash_concurrency() -> MaaResult<usize> {
+    get_env("PASSWORD_HASH_CONCURRENCY")
+        .map(|x| x.parse())
+        .and_then(|x| x.map_err(Into::into))
+}
+
+// 最多排队等待的密码哈希任务数
+pub fn password_hash_queue_size() -> MaaResult<usize> {
+    get_env("PASSWORD_HASH_QUEUE_SIZE")
+        .map(|x| x.parse())
+        .and_then(|x| x.map_err(Into::into))
+}
+
+// 可信的反向代理地址或网段, 逗号分隔, 例: `127.0.0.1,10.0.0.0/8`
+// 只有来自可信代理的请求才会使用 X-Forwarded-For 等请求头中的客户端 IP
+pub fn trusted_proxies() -> MaaResult<String> {
+    get_env("TRUSTED_PROXIES")
+}
+
 // 验证码过期时间
 pub fn vcode_expire_time() -> MaaResult<u64> {
     get_env("VCODE_EXPIRE_TIME")
@@ -54,6 +162,18 @@ pub fn vcode_expire_time() -> MaaResult<u64> {
         .and_then(|x| x.map_err(Into::into))
 }
 
+// GitHub API 令牌, 未配置时请求会受到更严格的限流
+pub fn github_token() -> MaaResult<String> {
+    get_env("GITHUB_TOKEN")
+}
+
+// 关卡数据同步间隔(秒), 为 0 时只在启动时同步一次
+pub fn level_sync_interval() -> MaaResult<u64> {
+    get_env("LEVEL_SYNC_INTERVAL")
+        .map(|x| x.parse())
+        .and_then(|x| x.map_err(Into::into))
+}
+
 // 邮件服务
 pub fn mail_host() -> MaaResult<String> {
     get_env("MAIL_HOST")
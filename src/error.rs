@@ -1,7 +1,7 @@
 use std::borrow::Cow;
 use std::fmt::Write;
 
-use http::StatusCode;
+use http::{header::RETRY_AFTER, StatusCode};
 
 use axum::response::{IntoResponse, Response};
 use thiserror::Error;
@@ -33,6 +33,39 @@ pub enum MaaError {
     #[error("Error hashing password: {0}")]
     BcryptError(#[from] bcrypt::BcryptError),
 
+    #[error("Error hashing password: {0}")]
+    Argon2Error(#[from] argon2::password_hash::Error),
+
+    #[error("Invalid config: {0}")]
+    ConfigError(String),
+
+    #[error("Invalid password hasher config: {0}")]
+    PasswordHasherError(String),
+
+    #[error("Error joining blocking task: {0}")]
+    TaskJoinError(#[from] tokio::task::JoinError),
+
+    #[error("TOTP error: {0}")]
+    TotpError(String),
+
+    #[error("HTTP request error: {0}")]
+    ReqwestError(#[from] reqwest::Error),
+
+    #[error("Error parsing json: {0}")]
+    JsonError(#[from] serde_json::Error),
+
+    #[error("Error syncing levels: {0}")]
+    LevelSyncError(String),
+
+    #[error("Git error: {0}")]
+    GitError(#[from] git2::Error),
+
+    #[error("IO error: {0}")]
+    IoError(#[from] std::io::Error),
+
+    #[error("Invalid JWT key config: {0}")]
+    JwtKeyError(String),
+
     #[error("Error parsing int: {0}")]
     ParseIntError(#[from] std::num::ParseIntError),
 
@@ -58,12 +91,24 @@ pub enum MaaError {
     #[error("用户未启用")]
     UserNotEnabled,
 
+    #[error("登录失败次数过多, 请在{retry_after}秒后重试")]
+    LoginLocked { retry_after: u64 },
+
     #[error("JWT验证失败")]
     JwtVerifyFailed,
 
+    #[error("请先登录")]
+    Unauthorized,
+
+    #[error("权限不足")]
+    PermissionDenied,
+
     #[error("用户id不存在")]
     NoneUserId,
 
+    #[error("用户不存在")]
+    UserNotFound,
+
     #[error("Validate失败: {0}")]
     ValidationError(#[from] validator::ValidationErrors),
 
@@ -75,6 +120,36 @@ pub enum MaaError {
 
     #[error("验证码不匹配")]
     VCodeNotMatch,
+
+    #[error("邮箱已被使用")]
+    EmailAlreadyInUse,
+
+    #[error("会话不存在")]
+    SessionNotFound,
+
+    #[error("两步验证码错误")]
+    TwoFactorCodeNotMatch,
+
+    #[error("已开启两步验证")]
+    TwoFactorAlreadyEnabled,
+
+    #[error("未开启两步验证")]
+    TwoFactorNotEnabled,
+
+    #[error("账户无需激活")]
+    UserAlreadyActivated,
+
+    #[error("令牌不存在")]
+    PersonalTokenNotFound,
+
+    #[error("令牌数量已达上限")]
+    TooManyPersonalTokens,
+
+    #[error("服务器繁忙, 请稍后重试")]
+    ServiceBusy,
+
+    #[error("关卡不存在")]
+    ArkLevelNotFound,
 }
 
 impl IntoResponse for MaaError {
@@ -85,13 +160,34 @@ impl IntoResponse for MaaError {
                 .body(self.to_string().into())
                 .unwrap_or_default(),
             MaaError::UserNotEnabled => Response::builder()
-                .status(10003)
+                .status(StatusCode::FORBIDDEN)
+                .body(self.to_string().into())
+                .unwrap_or_default(),
+            MaaError::LoginLocked { retry_after } => Response::builder()
+                .status(StatusCode::TOO_MANY_REQUESTS)
+                .header(RETRY_AFTER, retry_after.to_string())
+                .body(self.to_string().into())
+                .unwrap_or_default(),
+            MaaError::JwtVerifyFailed => Response::builder()
+                .status(StatusCode::UNAUTHORIZED)
+                .body(self.to_string().into())
+                .unwrap_or_default(),
+            MaaError::Unauthorized => Response::builder()
+                .status(StatusCode::UNAUTHORIZED)
+                .body(self.to_string().into())
+                .unwrap_or_default(),
+            MaaError::PermissionDenied => Response::builder()
+                .status(StatusCode::FORBIDDEN)
                 .body(self.to_string().into())
                 .unwrap_or_default(),
             MaaError::NoneUserId => Response::builder()
                 .status(StatusCode::BAD_REQUEST)
                 .body(self.to_string().into())
                 .unwrap_or_default(),
+            MaaError::UserNotFound => Response::builder()
+                .status(StatusCode::NOT_FOUND)
+                .body(self.to_string().into())
+                .unwrap_or_default(),
             MaaError::ValidationError(errors) => {
                 let field_errors = errors.field_errors();
                 let mut error_msg = String::new();
@@ -124,13 +220,54 @@ impl IntoResponse for MaaError {
                 .body(self.to_string().into())
                 .unwrap_or_default(),
             MaaError::RegistrationUserExist => Response::builder()
-                .status(10004)
+                .status(StatusCode::CONFLICT)
                 .body(self.to_string().into())
                 .unwrap_or_default(),
             MaaError::VCodeNotMatch => Response::builder()
                 .status(401)
                 .body(self.to_string().into())
                 .unwrap_or_default(),
+            MaaError::SessionNotFound => Response::builder()
+                .status(StatusCode::NOT_FOUND)
+                .body(self.to_string().into())
+                .unwrap_or_default(),
+            MaaError::EmailAlreadyInUse => Response::builder()
+                .status(StatusCode::CONFLICT)
+                .body(self.to_string().into())
+                .unwrap_or_default(),
+            MaaError::TwoFactorCodeNotMatch => Response::builder()
+                .status(StatusCode::UNAUTHORIZED)
+                .body(self.to_string().into())
+                .unwrap_or_default(),
+            MaaError::TwoFactorAlreadyEnabled => Response::builder()
+                .status(StatusCode::CONFLICT)
+                .body(self.to_string().into())
+                .unwrap_or_default(),
+            MaaError::TwoFactorNotEnabled => Response::builder()
+                .status(StatusCode::BAD_REQUEST)
+                .body(self.to_string().into())
+                .unwrap_or_default(),
+            MaaError::UserAlreadyActivated => Response::builder()
+                .status(StatusCode::CONFLICT)
+                .body(self.to_string().into())
+                .unwrap_or_default(),
+            MaaError::PersonalTokenNotFound => Response::builder()
+                .status(StatusCode::NOT_FOUND)
+                .body(self.to_string().into())
+                .unwrap_or_default(),
+            MaaError::TooManyPersonalTokens => Response::builder()
+                .status(StatusCode::BAD_REQUEST)
+                .body(self.to_string().into())
+                .unwrap_or_default(),
+            MaaError::ArkLevelNotFound => Response::builder()
+                .status(StatusCode::NOT_FOUND)
+                .body(self.to_string().into())
+                .unwrap_or_default(),
+            MaaError::ServiceBusy => Response::builder()
+                .status(StatusCode::SERVICE_UNAVAILABLE)
+                .header(RETRY_AFTER, "1")
+                .body(self.to_string().into())
+                .unwrap_or_default(),
             _ => {
                 tracing::error!("{}", self);
                 axum::http::StatusCode::INTERNAL_SERVER_ERROR.into_response()
@@ -5,23 +5,30 @@ pub mod route;
 pub mod service;
 pub mod util;
 
-use std::sync::Arc;
+use std::{path::PathBuf, sync::Arc};
 
 use bb8::Pool;
-use envs::{db_uri, log_dir, log_prefix, redis_uri};
+use envs::{admin_email, db_uri, log_dir, log_prefix, redis_uri};
 use error::MaaError;
-use mongodb::Client;
+use mongodb::{Client, Database};
 use repository::{
     ark_level_repository::ArkLevelRepository,
     redis_connection_manager::RedisConnectionManager,
     user_repository::UserRepository,
+    user_token_repository::UserTokenRepository,
 };
 use service::{
-    jwt_service::JwtService, mail_service::MailService,
+    ark_level_sync_service::{
+        invalidate_level_tree, sync_levels, ArkLevelSyncService,
+    },
+    jwt_service::JwtService,
+    level_source::open_local_source,
+    mail_service::MailService,
     user_service::UserService,
 };
 use tracing::Level;
 use tracing_appender::non_blocking::WorkerGuard;
+use util::permission::MaaRole;
 use util::redis_cache::RedisCache;
 
 pub type MaaResult<T> = Result<T, MaaError>;
@@ -29,7 +36,10 @@ pub type MaaResult<T> = Result<T, MaaError>;
 pub mod error;
 
 // We expect these env vars to be set at runtime so we can call expect here
-#[allow(clippy::expect_used)]
+#[allow(
+    clippy::expect_used,
+    reason = "logging must be initialized before anything else"
+)]
 pub fn init_logger() -> Option<WorkerGuard> {
     let log_dir = log_dir();
     let log_prefix = log_prefix();
@@ -58,9 +68,48 @@ pub fn init_logger() -> Option<WorkerGuard> {
     }
 }
 
+/// 初始化mongodb连接
+pub async fn connect_db() -> MaaResult<Database> {
+    // mongodb driver中自带connection pool，默认大小为10，如需调整可使用`ClientOptions::builder().max_pool_size()`
+    let uri = db_uri()?;
+    let client = Client::with_uri_str(&uri).await?;
+    let db = client
+        .default_database()
+        .ok_or(MaaError::NoDefaultDBError)?;
+
+    tracing::info!("Connected to database: {}", db.name());
+    Ok(db)
+}
+
+/// 初始化redis连接
+pub async fn connect_redis() -> MaaResult<RedisCache> {
+    let redis_uri = redis_uri()?;
+    let redis_client = redis::Client::open(redis_uri)?;
+    let redis_connection_manager = RedisConnectionManager::new(redis_client);
+    let redis_pool = Pool::builder().build(redis_connection_manager).await?;
+    Ok(RedisCache::new(redis_pool))
+}
+
+/// 从本地目录、git 仓库或 tarball 导入关卡数据到 `maa_level`, 返回更新的关卡数
+pub async fn import_levels(path: PathBuf) -> MaaResult<usize> {
+    let source =
+        tokio::task::spawn_blocking(move || open_local_source(&path)).await??;
+
+    let db = connect_db().await?;
+    let repository = ArkLevelRepository::new(&db);
+    repository.init_indexes().await?;
+    let count = sync_levels(source.as_ref(), &repository).await?;
+    if count > 0 {
+        invalidate_level_tree(&connect_redis().await?).await?;
+    }
+    Ok(count)
+}
+
 pub struct AppState {
     pub ark_level_repository: ArkLevelRepository,
+    pub ark_level_sync_service: Arc<ArkLevelSyncService>,
     pub user_service: UserService,
+    pub jwt_service: Arc<JwtService>,
     pub redis_cache: Arc<RedisCache>,
 }
 
@@ -68,28 +117,17 @@ pub type MaaAppState = Arc<AppState>;
 
 impl AppState {
     pub async fn new() -> MaaResult<Self> {
-        // 初始化mongodb连接
-        // mongodb driver中自带connection pool，默认大小为10，如需调整可使用`ClientOptions::builder().max_pool_size()`
-        let uri = db_uri()?;
-        let client = Client::with_uri_str(&uri).await?;
-        let db = client
-            .default_database()
-            .ok_or(MaaError::NoDefaultDBError)?;
+        let db = connect_db().await?;
 
-        tracing::info!("Connected to database: {}", db.name());
+        let redis_cache = connect_redis().await?;
+        let redis_cache = Arc::new(redis_cache);
 
         let ark_level_repository = ArkLevelRepository::new(&db);
-
-        // 初始化redis连接
-        let redis_uri = redis_uri()?;
-        let redis_client = redis::Client::open(redis_uri)?;
-        let redis_connection_manager =
-            RedisConnectionManager::new(redis_client);
-        let redis_pool =
-            Pool::builder().build(redis_connection_manager).await?;
-
-        let redis_cache = RedisCache::new(redis_pool);
-        let redis_cache = Arc::new(redis_cache);
+        ark_level_repository.init_indexes().await?;
+        let ark_level_sync_service = Arc::new(ArkLevelSyncService::new(
+            ArkLevelRepository::new(&db),
+            Arc::clone(&redis_cache),
+        ));
 
         let jwt_service = JwtService::new()?;
         let jwt_service = Arc::new(jwt_service);
@@ -105,15 +143,34 @@ impl AppState {
 
         // 初始化用户服务
         let user_repository = UserRepository::new(&db);
+        user_repository.init_indexes().await?;
+        let migrated = user_repository.migrate_legacy_roles().await?;
+        if migrated > 0 {
+            tracing::info!("Migrated roles of {} users", migrated);
+        }
+        if let Ok(email) = admin_email() {
+            if !user_repository
+                .update_role_by_email(&email, MaaRole::Admin)
+                .await?
+            {
+                tracing::warn!("Admin account {} does not exist", email);
+            }
+        }
+        let user_token_repository = UserTokenRepository::new(&db);
+        user_token_repository.init_indexes().await?;
         let user_service = UserService::new(
             user_repository,
+            user_token_repository,
             Arc::clone(&jwt_service),
             Arc::clone(&mail_service),
-        );
+            Arc::clone(&redis_cache),
+        )?;
 
         Ok(Self {
             ark_level_repository,
+            ark_level_sync_service,
             user_service,
+            jwt_service,
             redis_cache,
         })
     }
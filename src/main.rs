@@ -1,29 +1,63 @@
-use std::{net::SocketAddr, sync::Arc};
+use std::{net::SocketAddr, path::PathBuf, process::ExitCode, sync::Arc};
 
-use axum::{handler::Handler, routing::get, Extension, Router};
+use axum::{routing::get, Extension, Router};
 use maa_backend::{
-    init_logger,
-    middleware::{access_limit::AccessLimitLayer, cors_middleware},
-    route::{ark_level_handler::get_levels, user_handler::get_user_router},
+    import_levels, init_logger,
+    middleware::{cors_middleware, require_permission::require_permission},
+    route::{
+        admin_user_handler::get_admin_user_router,
+        ark_level_handler::get_ark_level_router, jwks_handler::get_jwks,
+        role_handler::get_role_router, user_handler::get_user_router,
+    },
+    util::permission::{USER_ASSIGN_ROLE, USER_MANAGE},
     AppState,
 };
 
 #[tokio::main]
-async fn main() {
+async fn main() -> ExitCode {
     let _guard = init_logger();
 
-    #[allow(clippy::expect_used)]
+    // 离线导入关卡数据: `maa_backend import-levels <目录|git 仓库|tarball>`
+    let mut args = std::env::args().skip(1);
+    if args.next().as_deref() == Some("import-levels") {
+        let Some(path) = args.next() else {
+            eprintln!("Usage: maa_backend import-levels <path>");
+            return ExitCode::FAILURE;
+        };
+        return match import_levels(PathBuf::from(&path)).await {
+            Ok(count) => {
+                tracing::info!("Imported {} levels from {}", count, path);
+                ExitCode::SUCCESS
+            }
+            Err(e) => {
+                tracing::error!("Failed to import levels: {}", e);
+                ExitCode::FAILURE
+            }
+        };
+    }
+
+    #[allow(clippy::expect_used, reason = "the server cannot run without it")]
     let app_state = AppState::new().await.expect("Failed to create app state");
 
     let app_state = Arc::new(app_state);
 
+    // 在后台同步关卡数据, 不阻塞服务启动
+    tokio::spawn(Arc::clone(&app_state.ark_level_sync_service).run());
+
     let app = Router::new()
         .route("/", get(|| async { "Hello, world!" }))
-        .route(
-            "/arknights/level",
-            get(get_levels.layer(AccessLimitLayer::new(10, 60))),
-        )
+        .nest("/arknights/level", get_ark_level_router())
+        .route("/.well-known/jwks.json", get(get_jwks))
         .nest("/user", get_user_router())
+        .nest(
+            "/admin/roles",
+            get_role_router().route_layer(require_permission(USER_ASSIGN_ROLE)),
+        )
+        .nest(
+            "/admin/users",
+            get_admin_user_router()
+                .route_layer(require_permission(USER_MANAGE)),
+        )
         .layer(cors_middleware())
         // for getting app state in middleware
         .layer(Extension(Arc::clone(&app_state)))
@@ -34,4 +68,5 @@ async fn main() {
     let listener = tokio::net::TcpListener::bind("0.0.0.0:3000").await.unwrap();
 
     axum::serve(listener, app).await.unwrap();
+    ExitCode::SUCCESS
 }
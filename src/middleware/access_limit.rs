@@ -2,7 +2,7 @@ use std::{future::Future, net::SocketAddr, pin::Pin, sync::Arc};
 
 use axum::{
     body::Body,
-    extract::{ConnectInfo, Request},
+    extract::{ConnectInfo, OriginalUri, Request},
     response::{IntoResponse, Response},
 };
 use http::StatusCode;
@@ -55,8 +55,14 @@ where
             .map(|c| c.0);
 
         let ip = request.get_ip_addr(remote_addr);
-        let request_uri = request.uri().to_string();
-        let key = format!("{}{}", ip, request_uri);
+        // 只按路径计数, 查询参数不同的请求共用同一个计数;
+        // 嵌套路由中 uri 不含前缀, 优先使用完整的原始路径
+        let path = request
+            .extensions()
+            .get::<OriginalUri>()
+            .map_or_else(|| request.uri().path(), |uri| uri.path())
+            .to_string();
+        let key = format!("{}{}", ip, path);
         let state = request
             .extensions()
             .get::<Arc<AppState>>()
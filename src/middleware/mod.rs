@@ -1,4 +1,5 @@
 pub mod access_limit;
+pub mod require_permission;
 
 use std::time::Duration;
 
@@ -8,7 +9,7 @@ use http::{
 };
 use tower_http::cors::CorsLayer;
 
-#[allow(clippy::expect_used)]
+#[allow(clippy::expect_used, reason = "the origin is a constant literal")]
 pub fn cors_middleware() -> CorsLayer {
     CorsLayer::new()
         .allow_methods([Method::GET, Method::POST, Method::DELETE, Method::PUT])
@@ -0,0 +1,99 @@
+use std::{future::Future, pin::Pin, sync::Arc};
+
+use axum::{
+    body::Body,
+    extract::Request,
+    response::{IntoResponse, Response},
+};
+use tower::{Layer, Service};
+
+use crate::{
+    route::extract::{bearer_token, AuthUser},
+    AppState, MaaError,
+};
+
+/// 要求请求携带拥有指定权限的 auth token, 例:
+///
+/// ```ignore
+/// Router::new()
+///     .route("/copilot/:id", delete(delete_copilot))
+///     .route_layer(require_permission(COPILOT_DELETE_ANY))
+/// ```
+///
+/// 校验通过后会将 `AuthUser` 放入请求扩展中, 供后续的 `AuthUser` 提取器复用
+pub fn require_permission(permission: &'static str) -> RequirePermissionLayer {
+    RequirePermissionLayer { permission }
+}
+
+#[derive(Clone)]
+pub struct RequirePermissionLayer {
+    permission: &'static str,
+}
+
+#[derive(Clone)]
+pub struct RequirePermissionService<S> {
+    inner: S,
+    permission: &'static str,
+}
+
+impl<S> Service<Request> for RequirePermissionService<S>
+where
+    S: Service<Request, Response = Response<Body>> + Clone + Send + 'static,
+    S::Future: Send,
+    S::Error: IntoResponse,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = Pin<
+        Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>,
+    >;
+
+    fn poll_ready(
+        &mut self,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut request: Request) -> Self::Future {
+        let state = request.extensions().get::<Arc<AppState>>().cloned();
+        let token = bearer_token(request.headers()).map(str::to_string);
+        let permission = self.permission;
+
+        // 鉴权是异步的, 按照 tower 的惯例换出已经 ready 的 inner
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+
+        Box::pin(async move {
+            let Some(state) = state else {
+                tracing::error!("AppState extension is missing");
+                return Ok(MaaError::PermissionDenied.into_response());
+            };
+            let Some(token) = token else {
+                return Ok(MaaError::Unauthorized.into_response());
+            };
+
+            let auth_user = match AuthUser::authenticate(&state, &token).await {
+                Ok(auth_user) => auth_user,
+                Err(e) => return Ok(e.into_response()),
+            };
+            if let Err(e) = auth_user.require_permission(permission) {
+                return Ok(e.into_response());
+            }
+
+            request.extensions_mut().insert(auth_user);
+            inner.call(request).await
+        })
+    }
+}
+
+impl<S> Layer<S> for RequirePermissionLayer {
+    type Service = RequirePermissionService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        RequirePermissionService {
+            inner,
+            permission: self.permission,
+        }
+    }
+}
@@ -1,9 +1,11 @@
-use bson::{doc, DateTime};
+use std::collections::HashSet;
+
+use bson::{doc, oid::ObjectId, DateTime, Document};
 use futures::{stream::TryStreamExt, StreamExt};
-use mongodb::{Collection, Database};
+use mongodb::{options::IndexOptions, Collection, Database, IndexModel};
 use serde::{Deserialize, Serialize};
 
-use crate::MaaResult;
+use crate::{util::regex_util::escape_regex, MaaResult};
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 #[serde(rename_all = "camelCase")]
@@ -15,7 +17,7 @@ pub struct ArkLevel {
     pub sha: String,
     // 地图类型, 例: 主线、活动、危机合约
     pub cat_one: Option<String>,
-    // 所属章节, 例: 怒号光明、寻昼行动
+    // 所属章节, 例: 怒号光明、寻昼行动; 同步的关卡由 levelId 得到, 例: 第1章、act18d0
     pub cat_two: Option<String>,
     // 地图ID, 例: 7-18、FC-1
     pub cat_three: Option<String>,
@@ -40,7 +42,7 @@ struct ArkLevelMongo {
     pub sha: String,
     // 地图类型, 例: 主线、活动、危机合约
     pub cat_one: Option<String>,
-    // 所属章节, 例: 怒号光明、寻昼行动
+    // 所属章节, 例: 怒号光明、寻昼行动; 同步的关卡由 levelId 得到, 例: 第1章、act18d0
     pub cat_two: Option<String>,
     // 地图ID, 例: 7-18、FC-1
     pub cat_three: Option<String>,
@@ -138,40 +140,309 @@ impl From<ArkLevelMongo> for ArkLevel {
     }
 }
 
+/// 关卡分类树的一个章节, 例: 主题曲 → 怒号光明 → [7-1, 7-2, ...]
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ArkLevelChapter {
+    pub cat_two: Option<String>,
+    pub count: u64,
+    pub stages: Vec<String>,
+}
+
+/// 关卡分类树的一个地图类型
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ArkLevelCategory {
+    pub cat_one: Option<String>,
+    pub count: u64,
+    pub chapters: Vec<ArkLevelChapter>,
+}
+
+/// 关卡列表的筛选条件, 为 None 的条件不参与筛选
+#[derive(Debug, Default, Clone)]
+pub struct ArkLevelFilter {
+    pub cat_one: Option<String>,
+    pub cat_two: Option<String>,
+    pub is_open: Option<bool>,
+}
+
+impl ArkLevelFilter {
+    fn to_document(&self) -> Document {
+        let mut filter = doc! {};
+        if let Some(cat_one) = &self.cat_one {
+            filter.insert("catOne", cat_one);
+        }
+        if let Some(cat_two) = &self.cat_two {
+            filter.insert("catTwo", cat_two);
+        }
+        match self.is_open {
+            Some(true) => filter.insert("isOpen", true),
+            // 未设置 isOpen 的关卡视为未开放
+            Some(false) => filter.insert("isOpen", doc! {"$ne": true}),
+            None => None,
+        };
+        filter
+    }
+}
+
 pub struct ArkLevelRepository {
     collection: Collection<ArkLevelMongo>,
+    // 不是有效关卡的文件, 以 sha 作为 _id, 同步时不再重复下载
+    skipped: Collection<Document>,
 }
 
 impl ArkLevelRepository {
     pub fn new(db: &Database) -> Self {
         let collection = db.collection("maa_level");
-        Self { collection }
+        let skipped = db.collection("maa_level_skipped");
+        Self {
+            collection,
+            skipped,
+        }
+    }
+
+    /// 创建索引, 同步关卡数据时以 levelId 作为唯一标识
+    pub async fn init_indexes(&self) -> MaaResult<()> {
+        let level_id_index = IndexModel::builder()
+            .keys(doc! {"levelId": 1})
+            .options(IndexOptions::builder().unique(true).build())
+            .build();
+        self.collection.create_index(level_id_index).await?;
+
+        let stage_id_index =
+            IndexModel::builder().keys(doc! {"stageId": 1}).build();
+        self.collection.create_index(stage_id_index).await?;
+
+        // 按分类筛选后以 levelId 作为游标分页
+        let category_index = IndexModel::builder()
+            .keys(doc! {"catOne": 1, "catTwo": 1, "levelId": 1})
+            .build();
+        self.collection.create_index(category_index).await?;
+        Ok(())
+    }
+
+    /// 所有关卡文件的 sha, 用于判断哪些文件需要重新下载
+    pub async fn find_all_shas(&self) -> MaaResult<HashSet<String>> {
+        let shas = self
+            .collection
+            .clone_with_type::<Document>()
+            .find(doc! {})
+            .projection(doc! {"_id": 0, "sha": 1})
+            .await?
+            .try_filter_map(|doc| async move {
+                Ok(doc.get_str("sha").ok().map(str::to_string))
+            })
+            .try_collect()
+            .await?;
+        Ok(shas)
+    }
+
+    /// 已跳过的关卡文件的 sha, 文件内容不变时无需重新下载
+    pub async fn find_skipped_shas(&self) -> MaaResult<HashSet<String>> {
+        let shas = self
+            .skipped
+            .find(doc! {})
+            .await?
+            .try_filter_map(|doc| async move {
+                Ok(doc.get_str("_id").ok().map(str::to_string))
+            })
+            .try_collect()
+            .await?;
+        Ok(shas)
+    }
+
+    /// 记录一个不是有效关卡的文件
+    pub async fn mark_skipped(&self, sha: &str) -> MaaResult<()> {
+        self.skipped
+            .update_one(doc! {"_id": sha}, doc! {"$setOnInsert": {"_id": sha}})
+            .upsert(true)
+            .await?;
+        Ok(())
+    }
+
+    /// 缺少章节的关卡的 levelId
+    pub async fn find_level_ids_without_cat_two(
+        &self,
+    ) -> MaaResult<Vec<String>> {
+        let level_ids = self
+            .collection
+            .clone_with_type::<Document>()
+            .find(doc! {"catTwo": null, "levelId": {"$type": "string"}})
+            .projection(doc! {"_id": 0, "levelId": 1})
+            .await?
+            .try_filter_map(|doc| async move {
+                Ok(doc.get_str("levelId").ok().map(str::to_string))
+            })
+            .try_collect()
+            .await?;
+        Ok(level_ids)
+    }
+
+    pub async fn update_cat_two(
+        &self,
+        level_id: &str,
+        cat_two: &str,
+    ) -> MaaResult<()> {
+        self.collection
+            .update_one(
+                doc! {"levelId": level_id},
+                doc! {"$set": {"catTwo": cat_two}},
+            )
+            .await?;
+        Ok(())
+    }
+
+    /// 按 levelId 插入或更新关卡, 新关卡会生成一个新的 id
+    ///
+    /// isOpen 与 closeTime 不来自关卡文件, 只在插入时写入, 同步不会覆盖已有的值
+    pub async fn upsert_level(&self, level: ArkLevel) -> MaaResult<()> {
+        let mut level = ArkLevelMongo::from(level);
+        level.id = None;
+        let filter = doc! {"levelId": &level.level_id};
+        let mut fields = bson::to_document(&level)?;
+        let mut on_insert = doc! {"_id": ObjectId::new().to_hex()};
+        for key in ["isOpen", "closeTime"] {
+            if let Some(value) = fields.remove(key) {
+                on_insert.insert(key, value);
+            }
+        }
+        let update = doc! {
+            "$set": fields,
+            "$setOnInsert": on_insert,
+        };
+        self.collection
+            .update_one(filter, update)
+            .upsert(true)
+            .await?;
+        Ok(())
     }
 
-    pub async fn query_all_levels(&self) -> MaaResult<Vec<ArkLevel>> {
-        let cursor = self.collection.find(doc! {}).await?;
+    pub async fn query_all_levels(
+        &self,
+        filter: &ArkLevelFilter,
+    ) -> MaaResult<Vec<ArkLevel>> {
+        let cursor = self.collection.find(filter.to_document()).await?;
         let result: Vec<ArkLevel> =
             cursor.map(|x| x.map(Into::into)).try_collect().await?;
         Ok(result)
     }
 
+    /// 多个关卡共用同一个 stageId 时, 返回 levelId 最小的关卡
+    pub async fn find_by_stage_id(
+        &self,
+        stage_id: &str,
+    ) -> MaaResult<Option<ArkLevel>> {
+        let level = self
+            .collection
+            .find_one(doc! {"stageId": stage_id})
+            .sort(doc! {"levelId": 1})
+            .await?;
+        Ok(level.map(Into::into))
+    }
+
+    /// 按 levelId 升序分页查询关卡, 返回 levelId 大于 `cursor` 的至多 `limit` 个关卡
+    ///
+    /// levelId 有唯一索引, 没有 levelId 的文档不参与分页;
+    /// 不使用 _id 是因为旧数据的 _id 可能是 ObjectId, 无法与字符串游标比较
+    pub async fn query_levels(
+        &self,
+        filter: &ArkLevelFilter,
+        cursor: Option<&str>,
+        limit: i64,
+    ) -> MaaResult<Vec<ArkLevel>> {
+        let mut filter = filter.to_document();
+        let level_id = match cursor {
+            Some(cursor) => doc! {"$gt": cursor},
+            None => doc! {"$type": "string"},
+        };
+        filter.insert("levelId", level_id);
+        let levels = self
+            .collection
+            .find(filter)
+            .sort(doc! {"levelId": 1})
+            .limit(limit)
+            .await?
+            .map_ok(Into::into)
+            .try_collect()
+            .await?;
+        Ok(levels)
+    }
+
+    /// 按 catOne → catTwo → catThree 聚合关卡, 没有关卡号的关卡不计入
+    pub async fn query_level_tree(&self) -> MaaResult<Vec<ArkLevelCategory>> {
+        let pipeline = [
+            doc! {"$match": {"catThree": {"$ne": null}}},
+            doc! {"$sort": {"catOne": 1, "catTwo": 1, "catThree": 1}},
+            doc! {"$group": {
+                // 字段缺失与 null 归为同一组
+                "_id": {
+                    "catOne": {"$ifNull": ["$catOne", null]},
+                    "catTwo": {"$ifNull": ["$catTwo", null]},
+                },
+                "count": {"$sum": 1},
+                "stages": {"$push": "$catThree"},
+            }},
+            doc! {"$sort": {"_id.catOne": 1, "_id.catTwo": 1}},
+            doc! {"$group": {
+                "_id": "$_id.catOne",
+                "count": {"$sum": "$count"},
+                "chapters": {"$push": {
+                    "cat_two": "$_id.catTwo",
+                    "count": "$count",
+                    "stages": "$stages",
+                }},
+            }},
+            doc! {"$sort": {"_id": 1}},
+            doc! {"$project": {
+                "_id": 0,
+                "cat_one": "$_id",
+                "count": 1,
+                "chapters": 1,
+            }},
+        ];
+        let tree = self
+            .collection
+            .aggregate(pipeline)
+            .with_type::<ArkLevelCategory>()
+            .await?
+            .try_collect()
+            .await?;
+        Ok(tree)
+    }
+
+    /// 按关键字模糊搜索关卡, stageId 或关卡号与关键字完全相同(忽略大小写)的排在前面
     pub async fn query_level_by_keyword(
         &self,
         keyword: &str,
+        limit: i64,
     ) -> MaaResult<Vec<ArkLevel>> {
-        let filter = doc! {"$regex": format!(".*{}.*",keyword),"$options": "i"};
-        let filter_doc = doc! {
-            "or": [
-                {"stageId": &filter},
-                {"catThree": &filter},
-                {"catTwo": &filter},
-                {"catOne": &filter},
-                {"name": &filter}
-            ]
-        };
-        let cursor = self.collection.find(filter_doc).await?;
-        let result: Vec<ArkLevel> =
-            cursor.map(|x| x.map(Into::into)).try_collect().await?;
+        let filter = doc! {"$regex": escape_regex(keyword), "$options": "i"};
+        let keyword = keyword.to_lowercase();
+        let pipeline = [
+            doc! {"$match": {
+                "$or": [
+                    {"stageId": &filter},
+                    {"catThree": &filter},
+                    {"catTwo": &filter},
+                    {"catOne": &filter},
+                    {"name": &filter}
+                ]
+            }},
+            doc! {"$addFields": {
+                "exactMatch": {"$or": [
+                    {"$eq": [{"$toLower": "$stageId"}, &keyword]},
+                    {"$eq": [{"$toLower": "$catThree"}, &keyword]}
+                ]}
+            }},
+            doc! {"$sort": {"exactMatch": -1, "stageId": 1}},
+            doc! {"$limit": limit},
+        ];
+        let result = self
+            .collection
+            .aggregate(pipeline)
+            .with_type::<ArkLevelMongo>()
+            .await?
+            .map_ok(Into::into)
+            .try_collect()
+            .await?;
         Ok(result)
     }
 
@@ -181,3 +452,18 @@ impl ArkLevelRepository {
         Ok(())
     }
 }
+
+#[test]
+fn t_level_filter_to_document() {
+    assert_eq!(ArkLevelFilter::default().to_document(), doc! {});
+
+    let filter = ArkLevelFilter {
+        cat_one: Some("主题曲".to_string()),
+        cat_two: None,
+        is_open: Some(false),
+    };
+    assert_eq!(
+        filter.to_document(),
+        doc! {"catOne": "主题曲", "isOpen": {"$ne": true}}
+    );
+}
@@ -1,5 +1,7 @@
 use reqwest::header::{HeaderMap, HeaderValue};
 
+use crate::MaaResult;
+
 #[derive(serde::Deserialize, Debug)]
 pub struct GithubTrees {
     pub sha: String,
@@ -26,6 +28,8 @@ pub struct GithubApi {
     token: Option<String>,
     /// GitHub API 的 URL
     api_url: String,
+    /// 下载仓库原始文件的 URL
+    raw_url: String,
     /// 仓库所有者
     owner: String,
     /// 仓库名称
@@ -65,6 +69,7 @@ impl GithubApi {
         GithubApi {
             token,
             api_url: "https://api.github.com".to_string(),
+            raw_url: "https://raw.githubusercontent.com".to_string(),
             owner,
             repo,
             default_headers: header_map,
@@ -129,4 +134,28 @@ impl GithubApi {
             }
         }
     }
+
+    /// 下载指定提交中的文件内容
+    ///
+    /// 使用 raw.githubusercontent.com 而不是 API, 不占用 API 的请求次数
+    pub async fn get_raw_file(
+        &self,
+        sha: &str,
+        path: &str,
+    ) -> MaaResult<String> {
+        let url = format!(
+            "{}/{}/{}/{}/{}",
+            self.raw_url, self.owner, self.repo, sha, path
+        );
+
+        let content = self
+            .client
+            .get(url)
+            .send()
+            .await?
+            .error_for_status()?
+            .text()
+            .await?;
+        Ok(content)
+    }
 }
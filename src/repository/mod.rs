@@ -2,3 +2,4 @@ pub mod ark_level_repository;
 pub mod github_api;
 pub mod redis_connection_manager;
 pub mod user_repository;
+pub mod user_token_repository;
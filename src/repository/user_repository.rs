@@ -1,8 +1,16 @@
-use bson::doc;
-use mongodb::{Collection, Database};
+use bson::{doc, oid::ObjectId, DateTime, Document};
+use futures::TryStreamExt;
+use mongodb::{
+    error::{ErrorKind, WriteFailure},
+    options::IndexOptions,
+    Collection, Database, IndexModel,
+};
 use serde::{Deserialize, Serialize};
 
-use crate::MaaResult;
+use crate::{
+    util::{permission::MaaRole, regex_util::escape_regex},
+    MaaError, MaaResult,
+};
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 #[serde(rename_all = "camelCase")]
@@ -11,8 +19,41 @@ pub struct MaaUser {
     pub user_name: String,
     pub email: String,
     pub password: String,
+    // 账户是否启用, 1 为启用
     pub status: i32,
+    // 注册后等待邮件激活, 激活前 status 为 0
+    #[serde(default)]
+    pub activation_pending: bool,
+    #[serde(default)]
+    pub role: MaaRole,
     pub refresh_jwt_ids: Vec<String>,
+    // refresh_jwt_ids 中每个 jti 对应的会话信息
+    pub sessions: Vec<MaaUserSession>,
+    // 注册时间
+    #[serde(default)]
+    pub create_time: Option<DateTime>,
+    // base32 编码的 TOTP 密钥, 未开启两步验证时为待确认的密钥
+    #[serde(default)]
+    pub totp_secret: Option<String>,
+    #[serde(default)]
+    pub totp_enabled: bool,
+    // 两步验证恢复码的 sha256 哈希, 每个恢复码只能使用一次
+    #[serde(default)]
+    pub recovery_codes: Vec<String>,
+    // 最近一次被接受的 TOTP 验证码的时间步, 同一时间步及更早的验证码不能再次使用
+    #[serde(default)]
+    pub totp_last_step: Option<i64>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct MaaUserSession {
+    // 会话对应的 refresh token jti
+    pub jti: String,
+    // 登录时间, unix 时间戳(秒)
+    pub issued_at: i64,
+    pub ip: String,
+    pub user_agent: String,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -23,8 +64,30 @@ pub struct MaaUserMongo {
     pub user_name: String,
     pub email: String,
     pub password: String,
+    // 账户是否启用, 1 为启用
     pub status: i32,
+    // 注册后等待邮件激活, 激活前 status 为 0
+    #[serde(default)]
+    pub activation_pending: bool,
+    #[serde(default)]
+    pub role: MaaRole,
     pub refresh_jwt_ids: Vec<String>,
+    #[serde(default)]
+    pub sessions: Vec<MaaUserSession>,
+    // 注册时间
+    #[serde(default)]
+    pub create_time: Option<DateTime>,
+    // base32 编码的 TOTP 密钥, 未开启两步验证时为待确认的密钥
+    #[serde(default)]
+    pub totp_secret: Option<String>,
+    #[serde(default)]
+    pub totp_enabled: bool,
+    // 两步验证恢复码的 sha256 哈希, 每个恢复码只能使用一次
+    #[serde(default)]
+    pub recovery_codes: Vec<String>,
+    // 最近一次被接受的 TOTP 验证码的时间步, 同一时间步及更早的验证码不能再次使用
+    #[serde(default)]
+    pub totp_last_step: Option<i64>,
 }
 
 impl MaaUser {
@@ -35,13 +98,82 @@ impl MaaUser {
             email: "unknown@unkown.unkown".to_string(),
             password: "unknown".to_string(),
             status: 0,
+            activation_pending: false,
+            role: MaaRole::User,
             refresh_jwt_ids: vec![],
+            sessions: vec![],
+            create_time: None,
+            totp_secret: None,
+            totp_enabled: false,
+            recovery_codes: vec![],
+            totp_last_step: None,
         }
     }
+
+    /// 注册时间, 早期没有记录注册时间的用户从 ObjectId 中推算
+    pub fn registered_at(&self) -> Option<DateTime> {
+        self.create_time.or_else(|| {
+            self.user_id
+                .as_deref()
+                .and_then(|id| ObjectId::parse_str(id).ok())
+                .map(|id| id.timestamp())
+        })
+    }
 }
 
+impl From<MaaUser> for MaaUserMongo {
+    fn from(val: MaaUser) -> Self {
+        MaaUserMongo {
+            user_id: val.user_id,
+            user_name: val.user_name,
+            email: val.email,
+            password: val.password,
+            status: val.status,
+            activation_pending: val.activation_pending,
+            role: val.role,
+            refresh_jwt_ids: val.refresh_jwt_ids,
+            sessions: val.sessions,
+            create_time: val.create_time,
+            totp_secret: val.totp_secret,
+            totp_enabled: val.totp_enabled,
+            recovery_codes: val.recovery_codes,
+            totp_last_step: val.totp_last_step,
+        }
+    }
+}
+
+impl From<MaaUserMongo> for MaaUser {
+    fn from(val: MaaUserMongo) -> Self {
+        MaaUser {
+            user_id: val.user_id,
+            user_name: val.user_name,
+            email: val.email,
+            password: val.password,
+            status: val.status,
+            activation_pending: val.activation_pending,
+            role: val.role,
+            refresh_jwt_ids: val.refresh_jwt_ids,
+            sessions: val.sessions,
+            create_time: val.create_time,
+            totp_secret: val.totp_secret,
+            totp_enabled: val.totp_enabled,
+            recovery_codes: val.recovery_codes,
+            totp_last_step: val.totp_last_step,
+        }
+    }
+}
+
+/// 判断是否为违反唯一索引导致的写入错误
+fn is_duplicate_key_error(e: &mongodb::error::Error) -> bool {
+    matches!(
+        e.kind.as_ref(),
+        ErrorKind::Write(WriteFailure::WriteError(err)) if err.code == 11000
+    )
+}
+
+#[derive(Clone)]
 pub struct UserRepository {
-    collection: Collection<MaaUser>,
+    collection: Collection<MaaUserMongo>,
 }
 
 impl UserRepository {
@@ -51,24 +183,377 @@ impl UserRepository {
         }
     }
 
+    /// 创建索引, 邮箱唯一以避免并发注册时产生重复账户,
+    /// 用户名索引用于前缀搜索
+    pub async fn init_indexes(&self) -> MaaResult<()> {
+        let email_index = IndexModel::builder()
+            .keys(doc! {"email": 1})
+            .options(IndexOptions::builder().unique(true).build())
+            .build();
+        self.collection.create_index(email_index).await?;
+
+        let user_name_index =
+            IndexModel::builder().keys(doc! {"userName": 1}).build();
+        self.collection.create_index(user_name_index).await?;
+        Ok(())
+    }
+
     pub async fn find_by_email(
         &self,
         email: &str,
     ) -> MaaResult<Option<MaaUser>> {
         let user = self.collection.find_one(doc! {"email": email}).await?;
-        Ok(user)
+        Ok(user.map(Into::into))
     }
 
     pub async fn find_by_user_id(
         &self,
         user_id: &str,
     ) -> MaaResult<Option<MaaUser>> {
-        let user = self.collection.find_one(doc! {"userId": user_id}).await?;
+        let user = self.collection.find_one(doc! {"_id": user_id}).await?;
+        Ok(user.map(Into::into))
+    }
+
+    /// 按用户名或邮箱模糊搜索用户, 返回当前页的用户以及总数
+    pub async fn search(
+        &self,
+        keyword: Option<&str>,
+        page: u64,
+        size: u64,
+    ) -> MaaResult<(Vec<MaaUser>, u64)> {
+        let filter = match keyword.filter(|k| !k.is_empty()) {
+            Some(keyword) => {
+                let regex =
+                    doc! {"$regex": escape_regex(keyword), "$options": "i"};
+                doc! {"$or": [{"userName": &regex}, {"email": &regex}]}
+            }
+            None => Document::new(),
+        };
+
+        let total = self.collection.count_documents(filter.clone()).await?;
+        let users = self
+            .collection
+            .find(filter)
+            .sort(doc! {"_id": 1})
+            .skip(page.saturating_sub(1).saturating_mul(size))
+            .limit(i64::try_from(size).unwrap_or(i64::MAX))
+            .await?
+            .map_ok(Into::into)
+            .try_collect()
+            .await?;
+
+        Ok((users, total))
+    }
+
+    /// 按用户名前缀搜索用户, 区分大小写以便使用索引
+    pub async fn find_by_user_name_prefix(
+        &self,
+        prefix: &str,
+        limit: i64,
+    ) -> MaaResult<Vec<MaaUser>> {
+        let filter = doc! {
+            "userName": {"$regex": format!("^{}", escape_regex(prefix))},
+        };
+        let users = self
+            .collection
+            .find(filter)
+            .sort(doc! {"userName": 1})
+            .limit(limit)
+            .await?
+            .map_ok(Into::into)
+            .try_collect()
+            .await?;
+        Ok(users)
+    }
+
+    /// 保存新用户, 未指定 id 时会生成一个新的 id, 返回保存后的用户
+    pub async fn save(&self, mut user: MaaUser) -> MaaResult<MaaUser> {
+        if user.user_id.is_none() {
+            user.user_id = Some(ObjectId::new().to_hex());
+        }
+        self.collection
+            .insert_one(MaaUserMongo::from(user.clone()))
+            .await
+            .map_err(|e| {
+                if is_duplicate_key_error(&e) {
+                    MaaError::RegistrationUserExist
+                } else {
+                    e.into()
+                }
+            })?;
         Ok(user)
     }
 
-    pub async fn save(&self, user: MaaUser) -> MaaResult<()> {
-        self.collection.insert_one(user).await?;
+    pub async fn update_password(
+        &self,
+        user_id: &str,
+        password: &str,
+    ) -> MaaResult<()> {
+        self.collection
+            .update_one(
+                doc! {"_id": user_id},
+                doc! {"$set": {"password": password}},
+            )
+            .await?;
+        Ok(())
+    }
+
+    pub async fn update_user_name(
+        &self,
+        user_id: &str,
+        user_name: &str,
+    ) -> MaaResult<()> {
+        self.collection
+            .update_one(
+                doc! {"_id": user_id},
+                doc! {"$set": {"userName": user_name}},
+            )
+            .await?;
+        Ok(())
+    }
+
+    pub async fn update_email(
+        &self,
+        user_id: &str,
+        email: &str,
+    ) -> MaaResult<()> {
+        self.collection
+            .update_one(doc! {"_id": user_id}, doc! {"$set": {"email": email}})
+            .await
+            .map_err(|e| {
+                if is_duplicate_key_error(&e) {
+                    MaaError::EmailAlreadyInUse
+                } else {
+                    e.into()
+                }
+            })?;
+        Ok(())
+    }
+
+    /// 修改账户状态, 管理员的操作会覆盖等待激活的状态
+    pub async fn update_status(
+        &self,
+        user_id: &str,
+        status: i32,
+    ) -> MaaResult<()> {
+        self.collection
+            .update_one(
+                doc! {"_id": user_id},
+                doc! {"$set": {"status": status, "activationPending": false}},
+            )
+            .await?;
+        Ok(())
+    }
+
+    /// 激活等待激活的账户, 账户不处于等待激活状态时返回 `false`
+    pub async fn activate(&self, user_id: &str) -> MaaResult<bool> {
+        let result = self
+            .collection
+            .update_one(
+                doc! {"_id": user_id, "activationPending": true},
+                doc! {"$set": {"status": 1, "activationPending": false}},
+            )
+            .await?;
+        Ok(result.modified_count == 1)
+    }
+
+    pub async fn update_role(
+        &self,
+        user_id: &str,
+        role: MaaRole,
+    ) -> MaaResult<()> {
+        let role = bson::to_bson(&role)?;
+        self.collection
+            .update_one(doc! {"_id": user_id}, doc! {"$set": {"role": role}})
+            .await?;
+        Ok(())
+    }
+
+    /// 修改指定邮箱的账户的角色, 账户不存在时返回 `false`
+    pub async fn update_role_by_email(
+        &self,
+        email: &str,
+        role: MaaRole,
+    ) -> MaaResult<bool> {
+        let role = bson::to_bson(&role)?;
+        let result = self
+            .collection
+            .update_one(doc! {"email": email}, doc! {"$set": {"role": role}})
+            .await?;
+        Ok(result.matched_count == 1)
+    }
+
+    /// 为没有角色的旧账户设置角色, 返回迁移的账户数
+    ///
+    /// 旧数据以 status 作为权限等级, status 不小于 2 的账户为管理员,
+    /// 迁移后 status 只表示账户是否启用
+    pub async fn migrate_legacy_roles(&self) -> MaaResult<u64> {
+        let admins = self
+            .collection
+            .update_many(
+                doc! {"role": {"$exists": false}, "status": {"$gte": 2}},
+                doc! {"$set": {
+                    "role": bson::to_bson(&MaaRole::Admin)?,
+                    "status": 1,
+                }},
+            )
+            .await?;
+        let users = self
+            .collection
+            .update_many(
+                doc! {"role": {"$exists": false}},
+                doc! {"$set": {"role": bson::to_bson(&MaaRole::User)?}},
+            )
+            .await?;
+        Ok(admins.modified_count + users.modified_count)
+    }
+
+    /// 添加一个会话, 超过 `max_login` 时移除最早的会话
+    pub async fn add_session(
+        &self,
+        user_id: &str,
+        session: &MaaUserSession,
+        max_login: usize,
+    ) -> MaaResult<()> {
+        let slice = -i64::try_from(max_login).unwrap_or(i64::MAX);
+        self.collection
+            .update_one(
+                doc! {"_id": user_id},
+                doc! {"$push": {
+                    "refreshJwtIds": {"$each": [&session.jti], "$slice": slice},
+                    "sessions": {"$each": [bson::to_bson(session)?], "$slice": slice},
+                }},
+            )
+            .await?;
+        Ok(())
+    }
+
+    /// 将会话的 jti 原子地替换为新的 jti, 会话不存在(已被轮换或移除)时返回 `false`
+    pub async fn rotate_session(
+        &self,
+        user_id: &str,
+        old_jti: &str,
+        new_jti: &str,
+    ) -> MaaResult<bool> {
+        let result = self
+            .collection
+            .update_one(
+                doc! {"_id": user_id, "refreshJwtIds": old_jti},
+                doc! {"$set": {
+                    "refreshJwtIds.$[id]": new_jti,
+                    "sessions.$[session].jti": new_jti,
+                }},
+            )
+            .array_filters([
+                doc! {"id": old_jti},
+                doc! {"session.jti": old_jti},
+            ])
+            .await?;
+        Ok(result.matched_count == 1)
+    }
+
+    /// 移除一个会话, 会话不存在时返回 `false`
+    pub async fn remove_session(
+        &self,
+        user_id: &str,
+        jti: &str,
+    ) -> MaaResult<bool> {
+        let result = self
+            .collection
+            .update_one(
+                doc! {"_id": user_id, "refreshJwtIds": jti},
+                doc! {"$pull": {
+                    "refreshJwtIds": jti,
+                    "sessions": {"jti": jti},
+                }},
+            )
+            .await?;
+        Ok(result.matched_count == 1)
+    }
+
+    /// 设置待确认的 TOTP 密钥以及恢复码, 两步验证在确认前不会生效,
+    /// `secret` 为 `None` 时关闭两步验证
+    pub async fn update_two_factor(
+        &self,
+        user_id: &str,
+        secret: Option<&str>,
+        recovery_codes: &[String],
+    ) -> MaaResult<()> {
+        self.collection
+            .update_one(
+                doc! {"_id": user_id},
+                doc! {"$set": {
+                    "totpSecret": secret,
+                    "totpEnabled": false,
+                    "recoveryCodes": recovery_codes,
+                    "totpLastStep": null,
+                }},
+            )
+            .await?;
+        Ok(())
+    }
+
+    /// 开启两步验证, 并记录确认时使用的验证码的时间步
+    pub async fn enable_two_factor(
+        &self,
+        user_id: &str,
+        step: i64,
+    ) -> MaaResult<()> {
+        self.collection
+            .update_one(
+                doc! {"_id": user_id},
+                doc! {"$set": {"totpEnabled": true, "totpLastStep": step}},
+            )
+            .await?;
+        Ok(())
+    }
+
+    /// 接受一个 TOTP 时间步, 该时间步不晚于上次接受的时间步时返回 `false`
+    pub async fn accept_totp_step(
+        &self,
+        user_id: &str,
+        step: i64,
+    ) -> MaaResult<bool> {
+        let result = self
+            .collection
+            .update_one(
+                doc! {
+                    "_id": user_id,
+                    "$or": [
+                        {"totpLastStep": null},
+                        {"totpLastStep": {"$lt": step}},
+                    ],
+                },
+                doc! {"$set": {"totpLastStep": step}},
+            )
+            .await?;
+        Ok(result.matched_count == 1)
+    }
+
+    /// 使用一个恢复码, 恢复码不存在或已被使用时返回 `false`
+    pub async fn consume_recovery_code(
+        &self,
+        user_id: &str,
+        code_hash: &str,
+    ) -> MaaResult<bool> {
+        let result = self
+            .collection
+            .update_one(
+                doc! {"_id": user_id, "recoveryCodes": code_hash},
+                doc! {"$pull": {"recoveryCodes": code_hash}},
+            )
+            .await?;
+        Ok(result.modified_count == 1)
+    }
+
+    /// 清空用户的所有会话
+    pub async fn clear_sessions(&self, user_id: &str) -> MaaResult<()> {
+        self.collection
+            .update_one(
+                doc! {"_id": user_id},
+                doc! {"$set": {"refreshJwtIds": [], "sessions": []}},
+            )
+            .await?;
         Ok(())
     }
 }
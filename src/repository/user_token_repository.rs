@@ -0,0 +1,190 @@
+use std::time::Duration;
+
+use bson::{doc, oid::ObjectId, DateTime};
+use futures::TryStreamExt;
+use mongodb::{options::IndexOptions, Collection, Database, IndexModel};
+use serde::{Deserialize, Serialize};
+
+use crate::MaaResult;
+
+/// 用户创建的个人访问令牌, 只保存令牌的哈希
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct MaaUserToken {
+    pub token_id: Option<String>,
+    pub user_id: String,
+    pub name: String,
+    // 令牌的 sha256 哈希
+    pub token_hash: String,
+    // 令牌可以使用的权限, 实际权限为其与用户当前角色权限的交集
+    pub scopes: Vec<String>,
+    pub create_time: DateTime,
+    // 为 None 时永不过期
+    pub expire_time: Option<DateTime>,
+    pub last_used_time: Option<DateTime>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct MaaUserTokenMongo {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub token_id: Option<String>,
+    pub user_id: String,
+    pub name: String,
+    pub token_hash: String,
+    pub scopes: Vec<String>,
+    pub create_time: DateTime,
+    pub expire_time: Option<DateTime>,
+    pub last_used_time: Option<DateTime>,
+}
+
+impl MaaUserToken {
+    pub fn is_expired(&self) -> bool {
+        self.expire_time
+            .is_some_and(|expire_time| expire_time <= DateTime::now())
+    }
+}
+
+impl From<MaaUserToken> for MaaUserTokenMongo {
+    fn from(val: MaaUserToken) -> Self {
+        MaaUserTokenMongo {
+            token_id: val.token_id,
+            user_id: val.user_id,
+            name: val.name,
+            token_hash: val.token_hash,
+            scopes: val.scopes,
+            create_time: val.create_time,
+            expire_time: val.expire_time,
+            last_used_time: val.last_used_time,
+        }
+    }
+}
+
+impl From<MaaUserTokenMongo> for MaaUserToken {
+    fn from(val: MaaUserTokenMongo) -> Self {
+        MaaUserToken {
+            token_id: val.token_id,
+            user_id: val.user_id,
+            name: val.name,
+            token_hash: val.token_hash,
+            scopes: val.scopes,
+            create_time: val.create_time,
+            expire_time: val.expire_time,
+            last_used_time: val.last_used_time,
+        }
+    }
+}
+
+pub struct UserTokenRepository {
+    collection: Collection<MaaUserTokenMongo>,
+}
+
+impl UserTokenRepository {
+    pub fn new(db: &Database) -> Self {
+        Self {
+            collection: db.collection("maa_user_token"),
+        }
+    }
+
+    /// 创建索引, 过期的令牌由 MongoDB 自动删除
+    pub async fn init_indexes(&self) -> MaaResult<()> {
+        let hash_index = IndexModel::builder()
+            .keys(doc! {"tokenHash": 1})
+            .options(IndexOptions::builder().unique(true).build())
+            .build();
+        self.collection.create_index(hash_index).await?;
+
+        let user_index = IndexModel::builder().keys(doc! {"userId": 1}).build();
+        self.collection.create_index(user_index).await?;
+
+        let expire_index = IndexModel::builder()
+            .keys(doc! {"expireTime": 1})
+            .options(
+                IndexOptions::builder()
+                    .expire_after(Duration::from_secs(0))
+                    .build(),
+            )
+            .build();
+        self.collection.create_index(expire_index).await?;
+        Ok(())
+    }
+
+    pub async fn find_by_hash(
+        &self,
+        token_hash: &str,
+    ) -> MaaResult<Option<MaaUserToken>> {
+        let token = self
+            .collection
+            .find_one(doc! {"tokenHash": token_hash})
+            .await?;
+        Ok(token.map(Into::into))
+    }
+
+    pub async fn find_by_user_id(
+        &self,
+        user_id: &str,
+    ) -> MaaResult<Vec<MaaUserToken>> {
+        let tokens = self
+            .collection
+            .find(doc! {"userId": user_id})
+            .sort(doc! {"createTime": -1})
+            .await?
+            .map_ok(Into::into)
+            .try_collect()
+            .await?;
+        Ok(tokens)
+    }
+
+    pub async fn count_by_user_id(&self, user_id: &str) -> MaaResult<u64> {
+        let count = self
+            .collection
+            .count_documents(doc! {"userId": user_id})
+            .await?;
+        Ok(count)
+    }
+
+    /// 保存新令牌, 返回保存后的令牌
+    pub async fn save(
+        &self,
+        mut token: MaaUserToken,
+    ) -> MaaResult<MaaUserToken> {
+        if token.token_id.is_none() {
+            token.token_id = Some(ObjectId::new().to_hex());
+        }
+        self.collection
+            .insert_one(MaaUserTokenMongo::from(token.clone()))
+            .await?;
+        Ok(token)
+    }
+
+    pub async fn update_last_used_time(&self, token_id: &str) -> MaaResult<()> {
+        self.collection
+            .update_one(
+                doc! {"_id": token_id},
+                doc! {"$set": {"lastUsedTime": DateTime::now()}},
+            )
+            .await?;
+        Ok(())
+    }
+
+    /// 删除用户的所有令牌
+    pub async fn delete_by_user_id(&self, user_id: &str) -> MaaResult<()> {
+        self.collection
+            .delete_many(doc! {"userId": user_id})
+            .await?;
+        Ok(())
+    }
+
+    /// 删除用户的一个令牌, 令牌不存在或不属于该用户时返回 `false`
+    pub async fn delete(
+        &self,
+        user_id: &str,
+        token_id: &str,
+    ) -> MaaResult<bool> {
+        let result = self
+            .collection
+            .delete_one(doc! {"_id": token_id, "userId": user_id})
+            .await?;
+        Ok(result.deleted_count == 1)
+    }
+}
@@ -0,0 +1,83 @@
+use std::sync::Arc;
+
+use axum::extract::{Json, Path, Query, State};
+use axum::routing::{get, post};
+use axum::Router;
+use axum_macros::debug_handler;
+
+use crate::{
+    util::permission::USER_ASSIGN_ROLE, AppState, MaaAppState, MaaResult,
+};
+
+use super::{
+    extract::AuthUser,
+    request::admin::{
+        AdminUserQuery, AssignRoleRequest, UpdateUserStatusRequest,
+    },
+    response::admin::{MaaAdminUserInfo, MaaAdminUserPage},
+};
+
+/// 用户管理, 需要在外层挂载 `require_permission(USER_MANAGE)`
+pub fn get_admin_user_router() -> Router<Arc<AppState>> {
+    Router::new()
+        .route("/", get(search_users))
+        .route("/:id", get(get_user))
+        .route("/:id/status", post(update_status))
+        .route("/:id/logout", post(force_logout))
+        .route("/:id/role", post(assign_role))
+}
+
+#[debug_handler]
+async fn search_users(
+    state: State<MaaAppState>,
+    Query(query): Query<AdminUserQuery>,
+) -> MaaResult<Json<MaaAdminUserPage>> {
+    state.user_service.admin_search_users(query).await.map(Json)
+}
+
+#[debug_handler]
+async fn get_user(
+    state: State<MaaAppState>,
+    Path(id): Path<String>,
+) -> MaaResult<Json<MaaAdminUserInfo>> {
+    state.user_service.admin_get_user(&id).await.map(Json)
+}
+
+#[debug_handler(state = MaaAppState)]
+async fn update_status(
+    state: State<MaaAppState>,
+    auth_user: AuthUser,
+    Path(id): Path<String>,
+    Json(req): Json<UpdateUserStatusRequest>,
+) -> MaaResult<()> {
+    state
+        .user_service
+        .admin_update_status(&auth_user.user, &id, req)
+        .await
+}
+
+#[debug_handler(state = MaaAppState)]
+async fn force_logout(
+    state: State<MaaAppState>,
+    auth_user: AuthUser,
+    Path(id): Path<String>,
+) -> MaaResult<()> {
+    state
+        .user_service
+        .admin_force_logout(&auth_user.user, &id)
+        .await
+}
+
+#[debug_handler(state = MaaAppState)]
+async fn assign_role(
+    state: State<MaaAppState>,
+    auth_user: AuthUser,
+    Path(id): Path<String>,
+    Json(req): Json<AssignRoleRequest>,
+) -> MaaResult<()> {
+    auth_user.require_permission(USER_ASSIGN_ROLE)?;
+    state
+        .user_service
+        .admin_assign_role(&auth_user.user, &id, req)
+        .await
+}
@@ -1,12 +1,134 @@
+use std::sync::Arc;
+
+use axum::extract::{Path, Query, State};
+use axum::handler::Handler;
+use axum::routing::get;
+use axum::{Json, Router};
+use axum_macros::debug_handler;
+use validator::Validate;
+
 use crate::{
-    repository::ark_level_repository::ArkLevelInfo, MaaAppState, MaaResult,
+    middleware::access_limit::AccessLimitLayer,
+    repository::ark_level_repository::{
+        ArkLevelCategory, ArkLevelFilter, ArkLevelInfo,
+    },
+    service::ark_level_sync_service::level_tree_cache_key,
+    AppState, MaaAppState, MaaError, MaaResult,
 };
-use axum::extract::State;
-use axum::Json;
 
+use super::{
+    request::ark_level::{ArkLevelListQuery, ArkLevelSearchQuery},
+    response::ark_level::{MaaArkLevelList, MaaArkLevelPage},
+};
+
+// 搜索结果的默认数量
+const DEFAULT_SEARCH_LIMIT: i64 = 20;
+// 关卡列表每页的默认数量
+const DEFAULT_PAGE_LIMIT: i64 = 50;
+// 同步时会切换分类树缓存的版本, 过期时间用于清理旧版本的缓存
+const LEVEL_TREE_CACHE_TTL: u64 = 24 * 60 * 60;
+
+pub fn get_ark_level_router() -> Router<Arc<AppState>> {
+    Router::new()
+        .route("/", get(get_levels.layer(AccessLimitLayer::new(10, 60))))
+        .route(
+            "/search",
+            get(search_levels.layer(AccessLimitLayer::new(30, 60))),
+        )
+        .route("/tree", get(get_level_tree))
+        .route("/:stage_id", get(get_level))
+}
+
+/// 按 levelId 分页返回关卡, `all=true` 时以数组返回所有符合条件的关卡
+#[debug_handler]
 pub async fn get_levels(
     state: State<MaaAppState>,
+    Query(query): Query<ArkLevelListQuery>,
+) -> MaaResult<Json<MaaArkLevelList>> {
+    query.validate()?;
+    let limit = query.limit.unwrap_or(DEFAULT_PAGE_LIMIT);
+    let filter = ArkLevelFilter {
+        cat_one: query.cat_one,
+        cat_two: query.cat_two,
+        is_open: query.is_open,
+    };
+    if query.all {
+        let levels =
+            state.ark_level_repository.query_all_levels(&filter).await?;
+        return Ok(Json(MaaArkLevelList::All(
+            levels.into_iter().map(Into::into).collect(),
+        )));
+    }
+
+    // 多查询一个用于判断是否还有下一页
+    let mut levels = state
+        .ark_level_repository
+        .query_levels(&filter, query.cursor.as_deref(), limit + 1)
+        .await?;
+    let has_next = levels.len() as i64 > limit;
+    levels.truncate(limit as usize);
+    let next_cursor = if has_next {
+        levels.last().and_then(|level| level.level_id.clone())
+    } else {
+        None
+    };
+
+    Ok(Json(MaaArkLevelList::Page(MaaArkLevelPage {
+        data: levels.into_iter().map(Into::into).collect(),
+        next_cursor,
+    })))
+}
+
+/// 按 cat_one → cat_two → [cat_three] 分组的关卡分类树
+#[debug_handler]
+async fn get_level_tree(
+    state: State<MaaAppState>,
+) -> MaaResult<Json<Vec<ArkLevelCategory>>> {
+    let cache_key = level_tree_cache_key(&state.redis_cache).await?;
+    let cached: Option<String> = state.redis_cache.get(&cache_key).await?;
+    if let Some(cached) = cached {
+        return Ok(Json(serde_json::from_str(&cached)?));
+    }
+
+    let tree = state.ark_level_repository.query_level_tree().await?;
+    state
+        .redis_cache
+        .set_ex(
+            &cache_key,
+            serde_json::to_string(&tree)?,
+            LEVEL_TREE_CACHE_TTL,
+        )
+        .await?;
+    Ok(Json(tree))
+}
+
+#[debug_handler]
+async fn get_level(
+    state: State<MaaAppState>,
+    Path(stage_id): Path<String>,
+) -> MaaResult<Json<ArkLevelInfo>> {
+    state
+        .ark_level_repository
+        .find_by_stage_id(&stage_id)
+        .await?
+        .map(|level| Json(level.into()))
+        .ok_or(MaaError::ArkLevelNotFound)
+}
+
+#[debug_handler]
+async fn search_levels(
+    state: State<MaaAppState>,
+    Query(mut query): Query<ArkLevelSearchQuery>,
 ) -> MaaResult<Json<Vec<ArkLevelInfo>>> {
-    let levels = state.ark_level_repository.query_all_levels().await?;
+    // 先去除首尾空白再校验, 避免只有空白的关键字匹配所有关卡
+    query.q = query.q.trim().to_string();
+    query.validate()?;
+    let levels = state
+        .ark_level_repository
+        .query_level_by_keyword(
+            &query.q,
+            query.limit.unwrap_or(DEFAULT_SEARCH_LIMIT),
+        )
+        .await?;
     Ok(Json(levels.into_iter().map(Into::into).collect()))
 }
@@ -0,0 +1,168 @@
+use std::{convert::Infallible, net::SocketAddr};
+
+use axum::{
+    async_trait,
+    extract::{ConnectInfo, FromRequestParts},
+};
+use http::{
+    header::{AUTHORIZATION, USER_AGENT},
+    request::Parts,
+    HeaderMap,
+};
+
+use crate::{
+    repository::user_repository::MaaUser, service::jwt_service::JwtAuthClaims,
+    util::request_ext::RequestExt, MaaAppState, MaaError, MaaResult,
+};
+
+/// 已登录的用户, 从 `Authorization: Bearer <token>` 请求头中解析,
+/// token 可以是 auth token 或者个人访问令牌
+///
+/// 未携带 token 时返回 `MaaError::Unauthorized`,
+/// 已经过 `RequirePermissionLayer` 校验的请求会直接复用其解析结果
+#[derive(Clone)]
+pub struct AuthUser {
+    pub user_id: String,
+    pub user: MaaUser,
+    pub claims: JwtAuthClaims,
+}
+
+/// 可选的登录用户, 未携带 token 时为 `None`
+///
+/// 携带了 token 但校验失败时仍然会拒绝请求, 以便客户端及时刷新 token
+pub struct OptionalAuthUser(pub Option<AuthUser>);
+
+impl AuthUser {
+    pub(crate) async fn authenticate(
+        state: &MaaAppState,
+        token: &str,
+    ) -> MaaResult<Self> {
+        let (user, claims) = state.user_service.authenticate(token).await?;
+        Ok(Self {
+            user_id: claims.sub.clone(),
+            user,
+            claims,
+        })
+    }
+
+    pub fn has_permission(&self, permission: &str) -> bool {
+        self.claims.auth.iter().any(|auth| auth == permission)
+    }
+
+    pub fn require_permission(&self, permission: &str) -> MaaResult<()> {
+        if self.has_permission(permission) {
+            Ok(())
+        } else {
+            Err(MaaError::PermissionDenied)
+        }
+    }
+
+    /// 要求通过登录获得的 auth token,
+    /// 个人访问令牌不能用于管理令牌、两步验证等账户安全相关的操作
+    pub fn require_login_session(&self) -> MaaResult<()> {
+        if self.claims.typ == "auth" {
+            Ok(())
+        } else {
+            Err(MaaError::PermissionDenied)
+        }
+    }
+}
+
+/// 客户端信息, 用于记录登录会话
+#[derive(Debug, Clone)]
+pub struct ClientInfo {
+    pub ip: String,
+    pub user_agent: String,
+}
+
+#[async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for ClientInfo {
+    type Rejection = Infallible;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        _state: &S,
+    ) -> Result<Self, Self::Rejection> {
+        let remote_addr = parts
+            .extensions
+            .get::<ConnectInfo<SocketAddr>>()
+            .map(|c| c.0);
+        let user_agent = parts
+            .headers
+            .get(USER_AGENT)
+            .and_then(|header| header.to_str().ok())
+            .unwrap_or_default()
+            .to_string();
+        Ok(Self {
+            ip: parts.get_ip_addr(remote_addr),
+            user_agent,
+        })
+    }
+}
+
+pub(crate) fn bearer_token(headers: &HeaderMap) -> Option<&str> {
+    headers
+        .get(AUTHORIZATION)
+        .and_then(|header| header.to_str().ok())
+        .and_then(|header| header.strip_prefix("Bearer "))
+        .map(str::trim)
+        .filter(|token| !token.is_empty())
+}
+
+#[async_trait]
+impl FromRequestParts<MaaAppState> for AuthUser {
+    type Rejection = MaaError;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &MaaAppState,
+    ) -> Result<Self, Self::Rejection> {
+        if let Some(auth_user) = parts.extensions.get::<AuthUser>() {
+            return Ok(auth_user.clone());
+        }
+        let token =
+            bearer_token(&parts.headers).ok_or(MaaError::Unauthorized)?;
+        AuthUser::authenticate(state, token).await
+    }
+}
+
+#[async_trait]
+impl FromRequestParts<MaaAppState> for OptionalAuthUser {
+    type Rejection = MaaError;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &MaaAppState,
+    ) -> Result<Self, Self::Rejection> {
+        if bearer_token(&parts.headers).is_none() {
+            return Ok(Self(None));
+        }
+        AuthUser::from_request_parts(parts, state)
+            .await
+            .map(|user| Self(Some(user)))
+    }
+}
+
+#[test]
+fn t_personal_token_is_not_login_session() {
+    let auth_user = |typ: &str| AuthUser {
+        user_id: "id".to_string(),
+        user: MaaUser::unknown(),
+        claims: JwtAuthClaims {
+            sub: "id".to_string(),
+            jti: None,
+            role: String::new(),
+            auth: Vec::new(),
+            iat: 0,
+            iat_ms: None,
+            exp: 0,
+            nbf: 0,
+            typ: typ.to_string(),
+        },
+    };
+    auth_user("auth").require_login_session().unwrap();
+    assert!(matches!(
+        auth_user("pat").require_login_session(),
+        Err(MaaError::PermissionDenied)
+    ));
+}
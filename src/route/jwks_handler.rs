@@ -0,0 +1,9 @@
+use axum::{extract::State, Json};
+use serde_json::Value;
+
+use crate::MaaAppState;
+
+/// 发布用于验证 MAA token 的公钥, 供其他服务使用
+pub async fn get_jwks(state: State<MaaAppState>) -> Json<Value> {
+    Json(state.jwt_service.jwks().clone())
+}
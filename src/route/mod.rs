@@ -1,4 +1,8 @@
+pub mod admin_user_handler;
 pub mod ark_level_handler;
+pub mod extract;
+pub mod jwks_handler;
 pub mod request;
 pub mod response;
+pub mod role_handler;
 pub mod user_handler;
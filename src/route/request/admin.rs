@@ -0,0 +1,28 @@
+use serde::Deserialize;
+use validator::Validate;
+
+use crate::util::permission::MaaRole;
+
+#[derive(Deserialize, Validate, Debug)]
+pub struct AdminUserQuery {
+    // 按用户名或邮箱模糊搜索
+    pub keyword: Option<String>,
+    #[validate(range(min = 1, message = "页码必须大于0"))]
+    pub page: Option<u64>,
+    #[validate(range(
+        min = 1,
+        max = 100,
+        message = "每页数量必须在1-100之间"
+    ))]
+    pub size: Option<u64>,
+}
+
+#[derive(Deserialize, Validate, Debug)]
+pub struct UpdateUserStatusRequest {
+    pub enabled: bool,
+}
+
+#[derive(Deserialize, Validate, Debug)]
+pub struct AssignRoleRequest {
+    pub role: MaaRole,
+}
@@ -0,0 +1,33 @@
+use serde::Deserialize;
+use validator::Validate;
+
+#[derive(Deserialize, Validate, Debug)]
+pub struct ArkLevelSearchQuery {
+    // 关卡号、地图名或分类关键字
+    #[validate(length(
+        min = 1,
+        max = 32,
+        message = "关键字长度必须在1-32之间"
+    ))]
+    pub q: String,
+    #[validate(range(min = 1, max = 50, message = "返回数量必须在1-50之间"))]
+    pub limit: Option<i64>,
+}
+
+#[derive(Deserialize, Validate, Debug)]
+pub struct ArkLevelListQuery {
+    pub cat_one: Option<String>,
+    pub cat_two: Option<String>,
+    pub is_open: Option<bool>,
+    // 上一页返回的 next_cursor
+    pub cursor: Option<String>,
+    #[validate(range(
+        min = 1,
+        max = 200,
+        message = "每页数量必须在1-200之间"
+    ))]
+    pub limit: Option<i64>,
+    // 兼容旧客户端, 为 true 时不分页, 以数组返回所有符合条件的关卡
+    #[serde(default)]
+    pub all: bool,
+}
@@ -1 +1,3 @@
+pub mod admin;
+pub mod ark_level;
 pub mod user;
@@ -2,11 +2,15 @@ use serde::Deserialize;
 use validator::Validate;
 
 #[derive(Deserialize, Validate, Debug)]
+#[serde(rename_all = "camelCase")]
 pub struct LoginRequest {
     #[validate(email(message = "邮箱格式错误"))]
     pub email: String,
     #[validate(length(min = 1, message = "请输入用户密码"))]
     pub password: String,
+    // 记住我, 签发有效期更长的 refresh token
+    #[serde(default)]
+    pub remember_me: bool,
 }
 
 #[derive(Deserialize, Validate, Debug)]
@@ -22,8 +26,9 @@ pub struct RegisterRequest {
     pub user_name: String,
     #[validate(length(min = 8, max = 32, message = "密码长度必须在8-32之间"))]
     pub password: String,
+    // 通过邮件激活账户时不需要验证码
     #[validate(length(min = 1, message = "请输入验证码"))]
-    pub registration_token: String,
+    pub registration_token: Option<String>,
 }
 
 #[derive(Deserialize, Validate, Debug)]
@@ -31,3 +36,129 @@ pub struct SendRegistrationTokenRequest {
     #[validate(email(message = "邮箱格式错误"))]
     pub email: String,
 }
+
+#[derive(Deserialize, Validate, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct RefreshTokenRequest {
+    #[validate(length(min = 1, message = "请输入refresh token"))]
+    pub refresh_token: String,
+}
+
+#[derive(Deserialize, Validate, Debug)]
+pub struct PasswordResetVCodeRequest {
+    #[validate(email(message = "邮箱格式错误"))]
+    pub email: String,
+}
+
+#[derive(Deserialize, Validate, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct PasswordResetRequest {
+    #[validate(email(message = "邮箱格式错误"))]
+    pub email: String,
+    #[validate(length(min = 1, message = "请输入验证码"))]
+    pub active_code: String,
+    #[validate(length(min = 8, max = 32, message = "密码长度必须在8-32之间"))]
+    pub password: String,
+}
+
+#[derive(Deserialize, Validate, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct UpdatePasswordRequest {
+    #[validate(length(min = 1, message = "请输入原密码"))]
+    pub original_password: String,
+    #[validate(length(min = 8, max = 32, message = "密码长度必须在8-32之间"))]
+    pub new_password: String,
+}
+
+#[derive(Deserialize, Validate, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct UpdateUserInfoRequest {
+    #[validate(length(
+        min = 4,
+        max = 24,
+        message = "用户名长度必须在4-20之间"
+    ))]
+    pub user_name: String,
+}
+
+#[derive(Deserialize, Validate, Debug)]
+pub struct ChangeEmailVCodeRequest {
+    #[validate(email(message = "邮箱格式错误"))]
+    pub email: String,
+}
+
+#[derive(Deserialize, Validate, Debug)]
+pub struct ChangeEmailRequest {
+    #[validate(email(message = "邮箱格式错误"))]
+    pub email: String,
+    #[validate(length(min = 1, message = "请输入验证码"))]
+    pub vcode: String,
+}
+
+#[derive(Deserialize, Validate, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct LogoutRequest {
+    #[validate(length(min = 1, message = "请输入refresh token"))]
+    pub refresh_token: String,
+}
+
+#[derive(Deserialize, Validate, Debug)]
+pub struct UserSearchQuery {
+    #[validate(length(
+        min = 1,
+        max = 24,
+        message = "用户名长度必须在1-24之间"
+    ))]
+    pub name: String,
+}
+
+#[derive(Deserialize, Validate, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct TwoFactorLoginRequest {
+    #[validate(length(min = 1, message = "请输入两步验证token"))]
+    pub two_factor_token: String,
+    #[validate(length(min = 1, max = 32, message = "请输入两步验证码"))]
+    pub code: String,
+}
+
+/// TOTP 验证码或恢复码
+#[derive(Deserialize, Validate, Debug)]
+pub struct TwoFactorCodeRequest {
+    #[validate(length(min = 1, max = 32, message = "请输入两步验证码"))]
+    pub code: String,
+}
+
+#[derive(Deserialize, Validate, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct CreatePersonalTokenRequest {
+    #[validate(length(
+        min = 1,
+        max = 32,
+        message = "令牌名称长度必须在1-32之间"
+    ))]
+    pub name: String,
+    #[validate(length(min = 1, message = "请至少选择一个权限"))]
+    pub scopes: Vec<String>,
+    // 有效天数, 不填时永不过期
+    #[validate(range(
+        min = 1,
+        max = 365,
+        message = "有效期必须在1-365天之间"
+    ))]
+    pub expires_in_days: Option<u32>,
+}
+
+#[derive(Deserialize, Validate, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ActivateAccountRequest {
+    #[validate(email(message = "邮箱格式错误"))]
+    pub email: String,
+    #[validate(length(min = 1, message = "请输入激活码"))]
+    pub activation_code: String,
+}
+
+#[derive(Deserialize, Validate, Debug)]
+pub struct ResendActivationRequest {
+    #[validate(email(message = "邮箱格式错误"))]
+    pub email: String,
+}
@@ -0,0 +1,41 @@
+use serde::Serialize;
+
+use crate::{repository::user_repository::MaaUser, util::permission::MaaRole};
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct MaaAdminUserInfo {
+    pub id: String,
+    pub user_name: String,
+    pub email: String,
+    pub status: i32,
+    pub activation_pending: bool,
+    pub role: MaaRole,
+    // 当前有效的登录会话数量
+    pub session_count: usize,
+    pub two_factor_enabled: bool,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct MaaAdminUserPage {
+    pub total: u64,
+    pub page: u64,
+    pub size: u64,
+    pub data: Vec<MaaAdminUserInfo>,
+}
+
+impl From<MaaUser> for MaaAdminUserInfo {
+    fn from(user: MaaUser) -> Self {
+        Self {
+            id: user.user_id.unwrap_or_default(),
+            user_name: user.user_name,
+            email: user.email,
+            status: user.status,
+            activation_pending: user.activation_pending,
+            role: user.role,
+            session_count: user.refresh_jwt_ids.len(),
+            two_factor_enabled: user.totp_enabled,
+        }
+    }
+}
@@ -0,0 +1,18 @@
+use serde::Serialize;
+
+use crate::repository::ark_level_repository::ArkLevelInfo;
+
+#[derive(Serialize, Debug)]
+pub struct MaaArkLevelPage {
+    pub data: Vec<ArkLevelInfo>,
+    // 下一页的游标, 为 None 时没有更多数据
+    pub next_cursor: Option<String>,
+}
+
+/// 关卡列表, 默认分页返回
+#[derive(Serialize, Debug)]
+#[serde(untagged)]
+pub enum MaaArkLevelList {
+    Page(MaaArkLevelPage),
+    All(Vec<ArkLevelInfo>),
+}
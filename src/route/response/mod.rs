@@ -1 +1,4 @@
+pub mod admin;
+pub mod ark_level;
+pub mod role;
 pub mod user;
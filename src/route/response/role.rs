@@ -0,0 +1,23 @@
+use serde::Serialize;
+
+use crate::util::permission::MaaRole;
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct MaaRoleInfo {
+    pub role: MaaRole,
+    pub permissions: Vec<String>,
+}
+
+impl From<MaaRole> for MaaRoleInfo {
+    fn from(role: MaaRole) -> Self {
+        Self {
+            role,
+            permissions: role
+                .permissions()
+                .iter()
+                .map(|permission| permission.to_string())
+                .collect(),
+        }
+    }
+}
@@ -1,6 +1,8 @@
 use serde::Serialize;
 
-use crate::repository::user_repository::MaaUser;
+use crate::repository::{
+    user_repository::MaaUser, user_token_repository::MaaUserToken,
+};
 
 #[derive(Serialize, Debug)]
 #[serde(rename_all = "camelCase")]
@@ -10,6 +12,18 @@ pub struct MaaUserInfo {
     pub activated: bool,
 }
 
+/// 公开的用户信息, 不能包含邮箱、会话等隐私信息
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct MaaUserProfile {
+    pub id: String,
+    pub user_name: String,
+    // 是否已完成邮件激活, 与账户是否被禁用无关
+    pub activated: bool,
+    // 注册时间, unix 时间戳(秒)
+    pub registered_at: Option<i64>,
+}
+
 #[derive(Serialize, Debug)]
 #[serde(rename_all = "camelCase")]
 pub struct MaaLoginResponse {
@@ -22,12 +36,114 @@ pub struct MaaLoginResponse {
     pub user_info: MaaUserInfo,
 }
 
+/// 登录结果, 开启两步验证的用户需要再提交验证码才能获得 token
+#[derive(Serialize, Debug)]
+#[serde(untagged)]
+pub enum MaaLoginResult {
+    Success(MaaLoginResponse),
+    TwoFactorRequired(MaaTwoFactorPending),
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct MaaTwoFactorPending {
+    // 总是为 true, 便于客户端区分两种登录结果
+    pub two_factor_required: bool,
+    pub two_factor_token: String,
+    pub valid_before: i64,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct MaaTwoFactorEnrollment {
+    pub secret: String,
+    pub otpauth_uri: String,
+    // 恢复码只在此时返回一次
+    pub recovery_codes: Vec<String>,
+}
+
+/// 注册结果, 通过邮件激活时只返回激活码的发送结果,
+/// 不论邮箱是否已注册响应都相同, 避免通过注册接口枚举账户
+#[derive(Serialize, Debug)]
+#[serde(untagged)]
+pub enum MaaRegisterResult {
+    Registered(MaaUserInfo),
+    ActivationRequired(SendVCodeResponse),
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct SendVCodeResponse {
+    /// 本次是否发送了验证码, 为 `false` 时表示发送过于频繁
+    pub sent: bool,
+    /// 距离允许再次发送的剩余秒数
+    pub retry_after: u64,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct MaaSessionInfo {
+    pub id: String,
+    pub issued_at: Option<i64>,
+    pub ip: Option<String>,
+    pub user_agent: Option<String>,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct MaaPersonalTokenInfo {
+    pub id: String,
+    pub name: String,
+    pub scopes: Vec<String>,
+    // 以下均为 unix 时间戳(秒)
+    pub created_at: i64,
+    pub expires_at: Option<i64>,
+    pub last_used_at: Option<i64>,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct MaaPersonalTokenCreated {
+    // 令牌明文只在创建时返回一次
+    pub token: String,
+    #[serde(flatten)]
+    pub info: MaaPersonalTokenInfo,
+}
+
 impl From<MaaUser> for MaaUserInfo {
     fn from(user: MaaUser) -> Self {
         Self {
             id: user.user_id.unwrap_or_default(),
             user_name: user.user_name,
-            activated: user.status == 1,
+            activated: !user.activation_pending,
+        }
+    }
+}
+
+impl From<MaaUser> for MaaUserProfile {
+    fn from(user: MaaUser) -> Self {
+        Self {
+            registered_at: user
+                .registered_at()
+                .map(|time| time.timestamp_millis() / 1000),
+            id: user.user_id.unwrap_or_default(),
+            user_name: user.user_name,
+            activated: !user.activation_pending,
+        }
+    }
+}
+
+impl From<MaaUserToken> for MaaPersonalTokenInfo {
+    fn from(token: MaaUserToken) -> Self {
+        Self {
+            id: token.token_id.unwrap_or_default(),
+            name: token.name,
+            scopes: token.scopes,
+            created_at: token.create_time.timestamp_millis() / 1000,
+            expires_at: token.expire_time.map(|t| t.timestamp_millis() / 1000),
+            last_used_at: token
+                .last_used_time
+                .map(|t| t.timestamp_millis() / 1000),
         }
     }
 }
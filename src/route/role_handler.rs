@@ -0,0 +1,20 @@
+use std::sync::Arc;
+
+use axum::routing::get;
+use axum::{Json, Router};
+use axum_macros::debug_handler;
+
+use crate::{util::permission::MaaRole, AppState};
+
+use super::response::role::MaaRoleInfo;
+
+/// 角色管理, 需要在外层挂载 `require_permission(USER_ASSIGN_ROLE)`
+pub fn get_role_router() -> Router<Arc<AppState>> {
+    Router::new().route("/", get(list_roles))
+}
+
+/// 列出所有角色及其拥有的权限
+#[debug_handler(state = Arc<AppState>)]
+async fn list_roles() -> Json<Vec<MaaRoleInfo>> {
+    Json(MaaRole::ALL.into_iter().map(Into::into).collect())
+}
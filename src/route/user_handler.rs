@@ -1,22 +1,356 @@
 use std::sync::Arc;
 
-use axum::extract::{Json, State};
-use axum::routing::post;
+use axum::extract::{Json, Path, Query, State};
+use axum::handler::Handler;
+use axum::routing::{delete, get, post};
 use axum::Router;
 use axum_macros::debug_handler;
 
-use crate::{AppState, MaaAppState, MaaResult};
+use crate::{
+    middleware::access_limit::AccessLimitLayer, AppState, MaaAppState,
+    MaaResult,
+};
 
-use super::{request::user::RegisterRequest, response::user::MaaUserInfo};
+use super::{
+    extract::{AuthUser, ClientInfo},
+    request::user::{
+        ActivateAccountRequest, ChangeEmailRequest, ChangeEmailVCodeRequest,
+        CreatePersonalTokenRequest, LoginRequest, LogoutRequest,
+        PasswordResetRequest, PasswordResetVCodeRequest, RefreshTokenRequest,
+        RegisterRequest, ResendActivationRequest, SendRegistrationTokenRequest,
+        TwoFactorCodeRequest, TwoFactorLoginRequest, UpdatePasswordRequest,
+        UpdateUserInfoRequest, UserSearchQuery,
+    },
+    response::user::{
+        MaaLoginResponse, MaaLoginResult, MaaPersonalTokenCreated,
+        MaaPersonalTokenInfo, MaaRegisterResult, MaaSessionInfo,
+        MaaTwoFactorEnrollment, MaaUserInfo, MaaUserProfile, SendVCodeResponse,
+    },
+};
 
 pub fn get_user_router() -> Router<Arc<AppState>> {
-    Router::new().route("/register", post(register))
+    Router::new()
+        .route("/login", post(login))
+        .route("/login/2fa", post(login_two_factor))
+        .route("/refresh", post(refresh))
+        .route("/logout", post(logout))
+        .route("/sessions", get(list_sessions))
+        .route("/sessions/:id", delete(revoke_session))
+        .route("/2fa/enroll", post(enroll_two_factor))
+        .route("/2fa/verify", post(confirm_two_factor))
+        .route("/2fa/disable", post(disable_two_factor))
+        .route(
+            "/tokens",
+            get(list_personal_tokens).post(create_personal_token),
+        )
+        .route("/tokens/:id", delete(revoke_personal_token))
+        .route("/search", get(search_users))
+        .route("/:id", get(get_user_profile))
+        .route("/register", post(register))
+        .route(
+            "/activate",
+            post(activate.layer(AccessLimitLayer::new(10, 60))),
+        )
+        .route(
+            "/activate/resend",
+            post(resend_activation_code.layer(AccessLimitLayer::new(5, 60))),
+        )
+        .route(
+            "/sendRegistrationToken",
+            post(send_registration_token.layer(AccessLimitLayer::new(5, 60))),
+        )
+        .route(
+            "/password/reset_request",
+            post(send_password_reset_vcode.layer(AccessLimitLayer::new(5, 60))),
+        )
+        .route(
+            "/password/reset",
+            post(reset_password.layer(AccessLimitLayer::new(10, 60))),
+        )
+        .route("/password/update", post(update_password))
+        .route("/update/info", post(update_user_info))
+        .route(
+            "/email/change_request",
+            post(send_change_email_vcode.layer(AccessLimitLayer::new(5, 60))),
+        )
+        .route(
+            "/email/change",
+            post(change_email.layer(AccessLimitLayer::new(10, 60))),
+        )
+}
+
+#[debug_handler]
+async fn login(
+    state: State<MaaAppState>,
+    client: ClientInfo,
+    Json(req): Json<LoginRequest>,
+) -> MaaResult<Json<MaaLoginResult>> {
+    state.user_service.login(req, client).await.map(Json)
+}
+
+#[debug_handler]
+async fn login_two_factor(
+    state: State<MaaAppState>,
+    client: ClientInfo,
+    Json(req): Json<TwoFactorLoginRequest>,
+) -> MaaResult<Json<MaaLoginResponse>> {
+    state
+        .user_service
+        .login_two_factor(req, client)
+        .await
+        .map(Json)
+}
+
+#[debug_handler]
+async fn refresh(
+    state: State<MaaAppState>,
+    Json(req): Json<RefreshTokenRequest>,
+) -> MaaResult<Json<MaaLoginResponse>> {
+    state.user_service.refresh_token(req).await.map(Json)
+}
+
+#[debug_handler(state = MaaAppState)]
+async fn logout(
+    state: State<MaaAppState>,
+    auth_user: AuthUser,
+    Json(req): Json<LogoutRequest>,
+) -> MaaResult<()> {
+    auth_user.require_login_session()?;
+    state
+        .user_service
+        .logout(auth_user.user, &auth_user.claims, req)
+        .await
+}
+
+#[debug_handler(state = MaaAppState)]
+async fn list_sessions(
+    state: State<MaaAppState>,
+    auth_user: AuthUser,
+) -> MaaResult<Json<Vec<MaaSessionInfo>>> {
+    auth_user.require_login_session()?;
+    Ok(Json(state.user_service.list_sessions(&auth_user.user)))
+}
+
+#[debug_handler(state = MaaAppState)]
+async fn revoke_session(
+    state: State<MaaAppState>,
+    auth_user: AuthUser,
+    Path(id): Path<String>,
+) -> MaaResult<()> {
+    auth_user.require_login_session()?;
+    state.user_service.revoke_session(auth_user.user, &id).await
 }
 
 #[debug_handler]
 async fn register(
     state: State<MaaAppState>,
     Json(req): Json<RegisterRequest>,
-) -> MaaResult<Json<MaaUserInfo>> {
+) -> MaaResult<Json<MaaRegisterResult>> {
     state.user_service.register(req).await.map(Json)
 }
+
+#[debug_handler]
+async fn activate(
+    state: State<MaaAppState>,
+    Json(req): Json<ActivateAccountRequest>,
+) -> MaaResult<()> {
+    state.user_service.activate(req).await
+}
+
+#[debug_handler]
+async fn resend_activation_code(
+    state: State<MaaAppState>,
+    Json(req): Json<ResendActivationRequest>,
+) -> MaaResult<Json<SendVCodeResponse>> {
+    state
+        .user_service
+        .resend_activation_code(req)
+        .await
+        .map(Json)
+}
+
+#[debug_handler]
+async fn send_registration_token(
+    state: State<MaaAppState>,
+    Json(req): Json<SendRegistrationTokenRequest>,
+) -> MaaResult<Json<SendVCodeResponse>> {
+    state
+        .user_service
+        .send_registration_token(req)
+        .await
+        .map(Json)
+}
+
+#[debug_handler]
+async fn send_password_reset_vcode(
+    state: State<MaaAppState>,
+    Json(req): Json<PasswordResetVCodeRequest>,
+) -> MaaResult<Json<SendVCodeResponse>> {
+    state
+        .user_service
+        .send_password_reset_vcode(req)
+        .await
+        .map(Json)
+}
+
+#[debug_handler]
+async fn reset_password(
+    state: State<MaaAppState>,
+    Json(req): Json<PasswordResetRequest>,
+) -> MaaResult<()> {
+    state.user_service.reset_password(req).await
+}
+
+#[debug_handler(state = MaaAppState)]
+async fn update_password(
+    state: State<MaaAppState>,
+    auth_user: AuthUser,
+    Json(req): Json<UpdatePasswordRequest>,
+) -> MaaResult<()> {
+    auth_user.require_login_session()?;
+    state
+        .user_service
+        .update_password(&auth_user.user, req)
+        .await
+}
+
+#[debug_handler(state = MaaAppState)]
+async fn update_user_info(
+    state: State<MaaAppState>,
+    auth_user: AuthUser,
+    Json(req): Json<UpdateUserInfoRequest>,
+) -> MaaResult<Json<MaaUserInfo>> {
+    auth_user.require_login_session()?;
+    state
+        .user_service
+        .update_user_info(auth_user.user, req)
+        .await
+        .map(Json)
+}
+
+#[debug_handler(state = MaaAppState)]
+async fn send_change_email_vcode(
+    state: State<MaaAppState>,
+    auth_user: AuthUser,
+    Json(req): Json<ChangeEmailVCodeRequest>,
+) -> MaaResult<Json<SendVCodeResponse>> {
+    auth_user.require_login_session()?;
+    state
+        .user_service
+        .send_change_email_vcode(req)
+        .await
+        .map(Json)
+}
+
+#[debug_handler(state = MaaAppState)]
+async fn change_email(
+    state: State<MaaAppState>,
+    auth_user: AuthUser,
+    Json(req): Json<ChangeEmailRequest>,
+) -> MaaResult<()> {
+    auth_user.require_login_session()?;
+    state.user_service.change_email(&auth_user.user, req).await
+}
+
+#[debug_handler(state = MaaAppState)]
+async fn enroll_two_factor(
+    state: State<MaaAppState>,
+    auth_user: AuthUser,
+) -> MaaResult<Json<MaaTwoFactorEnrollment>> {
+    auth_user.require_login_session()?;
+    state
+        .user_service
+        .enroll_two_factor(&auth_user.user)
+        .await
+        .map(Json)
+}
+
+#[debug_handler(state = MaaAppState)]
+async fn confirm_two_factor(
+    state: State<MaaAppState>,
+    auth_user: AuthUser,
+    Json(req): Json<TwoFactorCodeRequest>,
+) -> MaaResult<()> {
+    auth_user.require_login_session()?;
+    state
+        .user_service
+        .confirm_two_factor(&auth_user.user, req)
+        .await
+}
+
+#[debug_handler(state = MaaAppState)]
+async fn disable_two_factor(
+    state: State<MaaAppState>,
+    auth_user: AuthUser,
+    Json(req): Json<TwoFactorCodeRequest>,
+) -> MaaResult<()> {
+    auth_user.require_login_session()?;
+    state
+        .user_service
+        .disable_two_factor(&auth_user.user, req)
+        .await
+}
+
+#[debug_handler(state = MaaAppState)]
+async fn list_personal_tokens(
+    state: State<MaaAppState>,
+    auth_user: AuthUser,
+) -> MaaResult<Json<Vec<MaaPersonalTokenInfo>>> {
+    auth_user.require_login_session()?;
+    state
+        .user_service
+        .list_personal_tokens(&auth_user.user)
+        .await
+        .map(Json)
+}
+
+#[debug_handler(state = MaaAppState)]
+async fn create_personal_token(
+    state: State<MaaAppState>,
+    auth_user: AuthUser,
+    Json(req): Json<CreatePersonalTokenRequest>,
+) -> MaaResult<Json<MaaPersonalTokenCreated>> {
+    auth_user.require_login_session()?;
+    state
+        .user_service
+        .create_personal_token(&auth_user.user, req)
+        .await
+        .map(Json)
+}
+
+#[debug_handler(state = MaaAppState)]
+async fn revoke_personal_token(
+    state: State<MaaAppState>,
+    auth_user: AuthUser,
+    Path(id): Path<String>,
+) -> MaaResult<()> {
+    auth_user.require_login_session()?;
+    state
+        .user_service
+        .revoke_personal_token(&auth_user.user, &id)
+        .await
+}
+
+#[debug_handler]
+async fn get_user_profile(
+    state: State<MaaAppState>,
+    Path(user_id): Path<String>,
+) -> MaaResult<Json<MaaUserProfile>> {
+    state
+        .user_service
+        .get_user_profile(&user_id)
+        .await
+        .map(Json)
+}
+
+#[debug_handler]
+async fn search_users(
+    state: State<MaaAppState>,
+    Query(query): Query<UserSearchQuery>,
+) -> MaaResult<Json<Vec<MaaUserProfile>>> {
+    state
+        .user_service
+        .search_user_profiles(query)
+        .await
+        .map(Json)
+}
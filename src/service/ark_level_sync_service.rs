@@ -0,0 +1,293 @@
+use std::{sync::Arc, time::Duration};
+
+use futures::{stream, StreamExt};
+use serde::Deserialize;
+
+use crate::{
+    envs::{github_token, level_sync_interval},
+    repository::{
+        ark_level_repository::{ArkLevel, ArkLevelRepository},
+        github_api::GithubApi,
+    },
+    service::level_source::{GithubLevelSource, LevelFile, LevelSource},
+    util::redis_cache::RedisCache,
+    MaaResult,
+};
+
+// 关卡分类树的缓存版本, 关卡数据变化时加一, 缓存写在当前版本对应的 key 下
+const LEVEL_TREE_VERSION_KEY: &str = "ArkLevelTreeVersion";
+// 同时下载的关卡文件数
+const DOWNLOAD_CONCURRENCY: usize = 8;
+// 默认每小时同步一次
+const DEFAULT_SYNC_INTERVAL: u64 = 60 * 60;
+
+/// 关卡文件中用到的字段, 例:
+/// `{"code": "1-7", "levelId": "obt/main/level_main_01-07", "stageId": "main_01-07", ...}`
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct ArkTilePos {
+    code: Option<String>,
+    level_id: Option<String>,
+    stage_id: Option<String>,
+    name: Option<String>,
+    width: i32,
+    height: i32,
+}
+
+/// 从 MAA 资源仓库同步关卡数据到 `maa_level`
+pub struct ArkLevelSyncService {
+    source: Box<dyn LevelSource>,
+    repository: ArkLevelRepository,
+    redis_cache: Arc<RedisCache>,
+    interval: u64,
+}
+
+impl ArkLevelSyncService {
+    pub fn new(
+        repository: ArkLevelRepository,
+        redis_cache: Arc<RedisCache>,
+    ) -> Self {
+        let mut github_api = GithubApi::default();
+        if let Ok(token) = github_token() {
+            github_api.set_token(token);
+        }
+        Self {
+            source: Box::new(GithubLevelSource::new(github_api)),
+            repository,
+            redis_cache,
+            interval: level_sync_interval().unwrap_or(DEFAULT_SYNC_INTERVAL),
+        }
+    }
+
+    /// 启动时同步一次, 之后按照配置的间隔定时同步
+    pub async fn run(self: Arc<Self>) {
+        loop {
+            match self.sync().await {
+                Ok(count) => tracing::info!("Synced {} levels", count),
+                Err(e) => tracing::error!("Failed to sync levels: {}", e),
+            }
+            if self.interval == 0 {
+                break;
+            }
+            tokio::time::sleep(Duration::from_secs(self.interval)).await;
+        }
+    }
+
+    /// 同步资源仓库最新提交中的关卡文件, 返回更新的关卡数
+    pub async fn sync(&self) -> MaaResult<usize> {
+        let count = sync_levels(self.source.as_ref(), &self.repository).await?;
+        if count > 0 {
+            invalidate_level_tree(&self.redis_cache).await?;
+        }
+        Ok(count)
+    }
+}
+
+/// 当前版本的关卡分类树缓存 key
+///
+/// 请求在同步前读到旧版本时, 聚合出的旧数据只会写入旧版本的 key, 不会覆盖新版本的缓存
+pub async fn level_tree_cache_key(
+    redis_cache: &RedisCache,
+) -> MaaResult<String> {
+    let version: Option<u64> = redis_cache.get(LEVEL_TREE_VERSION_KEY).await?;
+    Ok(level_tree_key(version.unwrap_or_default()))
+}
+
+fn level_tree_key(version: u64) -> String {
+    format!("ArkLevelTree:{}", version)
+}
+
+/// 使关卡分类树的缓存失效, 下次请求时重新聚合, 需在关卡数据写入后调用
+pub async fn invalidate_level_tree(redis_cache: &RedisCache) -> MaaResult<()> {
+    let version = redis_cache.incr(LEVEL_TREE_VERSION_KEY).await?;
+    redis_cache.delete(&level_tree_key(version - 1)).await
+}
+
+/// 从关卡源同步关卡数据, 只读取 sha 发生变化的文件, 返回更新的关卡数
+pub async fn sync_levels(
+    source: &dyn LevelSource,
+    repository: &ArkLevelRepository,
+) -> MaaResult<usize> {
+    let files = source.list_files().await?;
+    let mut known = repository.find_all_shas().await?;
+    known.extend(repository.find_skipped_shas().await?);
+    let changed: Vec<LevelFile> = files
+        .into_iter()
+        .filter(|file| !known.contains(&file.sha))
+        .collect();
+    tracing::info!("Found {} changed level files", changed.len());
+
+    let results: Vec<MaaResult<bool>> = stream::iter(changed)
+        .map(|file| sync_file(source, repository, file))
+        .buffer_unordered(DOWNLOAD_CONCURRENCY)
+        .collect()
+        .await;
+
+    let mut count = 0;
+    for result in results {
+        match result {
+            Ok(true) => count += 1,
+            Ok(false) => {}
+            // 单个文件失败不影响其他文件, 下次同步时会重试
+            Err(e) => tracing::warn!("Failed to sync level file: {}", e),
+        }
+    }
+    Ok(count + backfill_chapters(repository).await?)
+}
+
+/// 为文件未变化、但保存时尚未解析章节的关卡补上章节, 返回更新的关卡数
+async fn backfill_chapters(
+    repository: &ArkLevelRepository,
+) -> MaaResult<usize> {
+    let mut count = 0;
+    for level_id in repository.find_level_ids_without_cat_two().await? {
+        if let Some(cat_two) = level_chapter(&level_id) {
+            repository.update_cat_two(&level_id, &cat_two).await?;
+            count += 1;
+        }
+    }
+    Ok(count)
+}
+
+/// 读取并保存一个关卡文件, 文件不是有效的关卡时返回 `false`
+async fn sync_file(
+    source: &dyn LevelSource,
+    repository: &ArkLevelRepository,
+    file: LevelFile,
+) -> MaaResult<bool> {
+    let content = source.read_file(&file).await?;
+    // 解析失败或缺少 levelId 的文件在内容变化前都不会成为有效关卡, 记录后不再下载
+    let level = match parse_level(&content, file.sha.clone()) {
+        Ok(Some(level)) => level,
+        Ok(None) => {
+            tracing::debug!("Skipping {}, no levelId", file.name);
+            repository.mark_skipped(&file.sha).await?;
+            return Ok(false);
+        }
+        Err(e) => {
+            tracing::warn!("Skipping {}, invalid level: {}", file.name, e);
+            repository.mark_skipped(&file.sha).await?;
+            return Ok(false);
+        }
+    };
+    repository.upsert_level(level).await?;
+    Ok(true)
+}
+
+/// 解析关卡文件, 缺少 levelId 的文件无法与已有数据对应, 返回 `None`
+pub fn parse_level(content: &str, sha: String) -> MaaResult<Option<ArkLevel>> {
+    let tile_pos: ArkTilePos = serde_json::from_str(content)?;
+    if tile_pos.level_id.is_none() {
+        return Ok(None);
+    }
+    Ok(Some(ArkLevel {
+        id: None,
+        cat_one: tile_pos
+            .level_id
+            .as_deref()
+            .and_then(level_category)
+            .map(str::to_string),
+        cat_two: tile_pos.level_id.as_deref().and_then(level_chapter),
+        cat_three: tile_pos.code,
+        level_id: tile_pos.level_id,
+        stage_id: tile_pos.stage_id,
+        sha,
+        name: tile_pos.name,
+        width: tile_pos.width,
+        height: tile_pos.height,
+        is_open: None,
+        close_time: None,
+    }))
+}
+
+/// 拆分 levelId 为小写的关卡类型和其后的路径, 例:
+/// `Obt/Main/level_main_01-07` 为 `("main", ["level_main_01-07"])`,
+/// `Activities/ACT18D0/level_act18d0_01` 为 `("activities", ["ACT18D0", "level_act18d0_01"])`
+fn split_level_id(level_id: &str) -> Option<(String, Vec<&str>)> {
+    let mut parts = level_id.split('/');
+    let first = parts.next()?;
+    let kind = if first.eq_ignore_ascii_case("obt") {
+        parts.next()?
+    } else {
+        first
+    };
+    Some((kind.to_ascii_lowercase(), parts.collect()))
+}
+
+/// 根据 levelId 判断地图类型, 例: `obt/main/level_main_01-07` 为主题曲
+fn level_category(level_id: &str) -> Option<&'static str> {
+    let (kind, _) = split_level_id(level_id)?;
+    let category = match kind.as_str() {
+        "main" | "hard" => "主题曲",
+        "activities" => "活动关卡",
+        "weekly" | "promote" => "资源收集",
+        "campaign" => "剿灭作战",
+        "rune" => "危机合约",
+        "memory" => "悖论模拟",
+        "training" => "训练关卡",
+        "roguelike" => "集成战略",
+        "legion" => "保全派驻",
+        _ => return None,
+    };
+    Some(category)
+}
+
+/// 根据 levelId 判断地图所属章节, 主题曲为章节号, 例: `obt/main/level_main_01-07` 为第1章,
+/// 资源收集为小写的关卡种类, 例: `obt/weekly/level_weekly_fly_1` 为 `fly`,
+/// 活动等带有子目录的关卡为小写的子目录名(活动 id), 例: `Activities/ACT18D0/level_act18d0_01` 为 `act18d0`
+///
+/// 关卡文件中没有活动的显示名称, 活动 id 在不同来源中大小写不一, 统一为小写以免同一活动被分为两组
+fn level_chapter(level_id: &str) -> Option<String> {
+    let (kind, path) = split_level_id(level_id)?;
+    match (kind.as_str(), path.as_slice()) {
+        ("main" | "hard", [file]) => {
+            let episode: u32 =
+                file.rsplit('_').next()?.split('-').next()?.parse().ok()?;
+            if episode == 0 {
+                Some("序章".to_string())
+            } else {
+                Some(format!("第{}章", episode))
+            }
+        }
+        // level_weekly_fly_1, level_promote_a01_1
+        ("weekly" | "promote", [file]) => {
+            file.split('_').nth(2).map(str::to_ascii_lowercase)
+        }
+        (_, [dir, _]) => Some(dir.to_ascii_lowercase()),
+        _ => None,
+    }
+}
+
+#[test]
+fn t_parse_level() {
+    let content = r#"{
+        "code": "1-7",
+        "height": 8,
+        "levelId": "obt/main/level_main_01-07",
+        "name": "暴君",
+        "stageId": "main_01-07",
+        "width": 11,
+        "tiles": []
+    }"#;
+    let level = parse_level(content, "abc".to_string()).unwrap().unwrap();
+    assert_eq!(level.level_id.as_deref(), Some("obt/main/level_main_01-07"));
+    assert_eq!(level.stage_id.as_deref(), Some("main_01-07"));
+    assert_eq!(level.cat_one.as_deref(), Some("主题曲"));
+    assert_eq!(level.cat_two.as_deref(), Some("第1章"));
+    assert_eq!(level.cat_three.as_deref(), Some("1-7"));
+    assert_eq!((level.width, level.height), (11, 8));
+    assert_eq!(level.sha, "abc");
+
+    let activity = r#"{"code": "GT-1", "levelId": "Activities/ACT9D0/level_act9d0_01", "width": 9, "height": 6}"#;
+    let level = parse_level(activity, String::new()).unwrap().unwrap();
+    assert_eq!(level.cat_one.as_deref(), Some("活动关卡"));
+    assert_eq!(level.cat_two.as_deref(), Some("act9d0"));
+
+    let weekly = r#"{"levelId": "Obt/Weekly/level_weekly_fly_1", "width": 9, "height": 6}"#;
+    let level = parse_level(weekly, String::new()).unwrap().unwrap();
+    assert_eq!(level.cat_one.as_deref(), Some("资源收集"));
+    assert_eq!(level.cat_two.as_deref(), Some("fly"));
+
+    let overview = r#"{"width": 0, "height": 0}"#;
+    assert!(parse_level(overview, String::new()).unwrap().is_none());
+}
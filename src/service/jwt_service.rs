@@ -1,16 +1,123 @@
-use jsonwebtokens::{Algorithm, AlgorithmID, Verifier};
+use std::collections::HashMap;
+
+use jsonwebtokens::{
+    raw::decode_header_only, Algorithm, AlgorithmID, Verifier,
+};
 use serde::{Deserialize, Serialize};
-use serde_json::json;
+use serde_json::{json, Value};
 
 use crate::{
-    envs::{jwt_expire_time, jwt_key},
+    envs::{
+        jwt_algorithm, jwt_auth_expire_time, jwt_expire_time, jwt_key,
+        jwt_key_id, jwt_previous_keys, jwt_private_key_file,
+        jwt_public_key_file, jwt_refresh_expire_time,
+        jwt_refresh_remember_expire_time,
+    },
+    util::jwk::public_key_to_jwk,
     MaaError, MaaResult,
 };
 
+// refresh token 默认有效期 7 天
+const DEFAULT_REFRESH_EXPIRE_TIME: u64 = 7 * 24 * 60 * 60;
+// 勾选"记住我"时 refresh token 默认有效期 30 天
+const DEFAULT_REMEMBER_REFRESH_EXPIRE_TIME: u64 = 30 * 24 * 60 * 60;
+// 两步验证 token 有效期 5 分钟
+const TWO_FACTOR_EXPIRE_TIME: u64 = 5 * 60;
+
+/// JWT 签名配置
+pub struct JwtConfig {
+    /// 签名算法, 支持 HS256、ES256、RS256
+    pub algorithm: AlgorithmID,
+    /// 写入 token header 的 kid
+    pub key_id: String,
+    /// HS256 时为密钥, ES256/RS256 时为 PEM 格式私钥
+    pub signing_key: Vec<u8>,
+    /// ES256/RS256 时为 PEM 格式公钥, HS256 时不使用
+    pub public_key: Option<Vec<u8>>,
+    /// 密钥轮换期间仍然接受的旧密钥 (kid, 密钥),
+    /// HS256 时为密钥, ES256/RS256 时为 PEM 格式公钥
+    pub previous_keys: Vec<(String, Vec<u8>)>,
+    /// auth token 有效期(秒)
+    pub auth_expire_time: u64,
+    /// refresh token 有效期(秒)
+    pub refresh_expire_time: u64,
+    /// 勾选"记住我"时的 refresh token 有效期(秒)
+    pub remember_refresh_expire_time: u64,
+}
+
+impl JwtConfig {
+    pub fn from_env() -> MaaResult<Self> {
+        let algorithm = match jwt_algorithm()
+            .unwrap_or_else(|_| "HS256".to_string())
+            .as_str()
+        {
+            "HS256" => AlgorithmID::HS256,
+            "ES256" => AlgorithmID::ES256,
+            "RS256" => AlgorithmID::RS256,
+            other => {
+                return Err(MaaError::JwtKeyError(format!(
+                    "unsupported algorithm: {}",
+                    other
+                )))
+            }
+        };
+        let is_hmac = algorithm == AlgorithmID::HS256;
+
+        let key_id = jwt_key_id().unwrap_or_else(|_| "default".to_string());
+
+        let (signing_key, public_key) = if is_hmac {
+            (jwt_key()?.into_bytes(), None)
+        } else {
+            (
+                std::fs::read(jwt_private_key_file()?)?,
+                Some(std::fs::read(jwt_public_key_file()?)?),
+            )
+        };
+
+        let mut previous_keys = Vec::new();
+        if let Ok(keys) = jwt_previous_keys() {
+            for entry in keys.split(',').filter(|entry| !entry.is_empty()) {
+                let (kid, value) = entry.split_once('=').ok_or_else(|| {
+                    MaaError::JwtKeyError(format!(
+                        "invalid previous key entry: {}",
+                        entry
+                    ))
+                })?;
+                let key = if is_hmac {
+                    value.as_bytes().to_vec()
+                } else {
+                    std::fs::read(value)?
+                };
+                previous_keys.push((kid.to_string(), key));
+            }
+        }
+
+        Ok(Self {
+            algorithm,
+            key_id,
+            signing_key,
+            public_key,
+            previous_keys,
+            auth_expire_time: jwt_auth_expire_time()
+                .or_else(|_| jwt_expire_time())?,
+            refresh_expire_time: jwt_refresh_expire_time()
+                .unwrap_or(DEFAULT_REFRESH_EXPIRE_TIME),
+            remember_refresh_expire_time: jwt_refresh_remember_expire_time()
+                .unwrap_or(DEFAULT_REMEMBER_REFRESH_EXPIRE_TIME),
+        })
+    }
+}
+
 pub struct JwtService {
     algorithm: Algorithm,
+    key_id: String,
+    // kid -> 用于验证的算法, 包含当前密钥以及轮换期间的旧密钥
+    verifying_keys: HashMap<String, Algorithm>,
+    jwks: Value,
     verifier: Verifier,
-    expire_time: u64,
+    auth_expire_time: u64,
+    refresh_expire_time: u64,
+    remember_refresh_expire_time: u64,
 }
 
 #[derive(Debug)]
@@ -20,19 +127,33 @@ pub struct SignedJwt {
     pub not_before: i64,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct JwtAuthClaims {
     pub sub: String,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub jti: Option<String>,
+    // 用户角色, 例: user、uploader、moderator、admin
+    #[serde(default)]
+    pub role: String,
+    // 角色拥有的权限
     #[serde(rename = "Authorities")]
     pub auth: Vec<String>,
     pub iat: i64,
+    // 精确到毫秒的签发时间, 用于与吊销水位线比较, 旧 token 没有该字段
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub iat_ms: Option<i64>,
     pub exp: i64,
     pub nbf: i64,
     pub typ: String,
 }
 
+impl JwtAuthClaims {
+    /// 签发时间(毫秒), 没有 `iat_ms` 时按 `iat` 所在秒的开始计算
+    pub fn issued_at_millis(&self) -> i64 {
+        self.iat_ms.unwrap_or(self.iat * 1000)
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 pub struct JwtRefreshClaims {
     pub sub: String,
@@ -44,46 +165,118 @@ pub struct JwtRefreshClaims {
     pub typ: String,
 }
 
+/// 密码验证通过、等待提交两步验证码时签发的 token
+#[derive(Serialize, Deserialize, Debug)]
+pub struct JwtTwoFactorClaims {
+    pub sub: String,
+    // 登录时是否勾选了"记住我", 完成两步验证后沿用
+    #[serde(default)]
+    pub remember_me: bool,
+    pub iat: i64,
+    pub exp: i64,
+    pub nbf: i64,
+    pub typ: String,
+}
+
 impl JwtService {
     pub fn new() -> MaaResult<Self> {
-        let jwt_key = jwt_key()?.as_bytes().to_vec();
+        Self::from_config(JwtConfig::from_env()?)
+    }
 
-        let expire_time = jwt_expire_time()?;
+    pub fn from_config(config: JwtConfig) -> MaaResult<Self> {
+        let id = config.algorithm;
 
-        let alg = Algorithm::new_hmac(AlgorithmID::ES256, jwt_key)?;
+        let (algorithm, current_public_key) = match id {
+            AlgorithmID::HS256 => (
+                Algorithm::new_hmac(id, config.signing_key.clone())?,
+                config.signing_key,
+            ),
+            AlgorithmID::ES256 | AlgorithmID::RS256 => {
+                let public_key = config.public_key.ok_or_else(|| {
+                    MaaError::JwtKeyError("missing public key".to_string())
+                })?;
+                let signer = if id == AlgorithmID::ES256 {
+                    Algorithm::new_ecdsa_pem_signer(id, &config.signing_key)?
+                } else {
+                    Algorithm::new_rsa_pem_signer(id, &config.signing_key)?
+                };
+                (signer, public_key)
+            }
+            other => {
+                return Err(MaaError::JwtKeyError(format!(
+                    "unsupported algorithm: {:?}",
+                    other
+                )))
+            }
+        };
+
+        let mut verifying_keys = HashMap::new();
+        let mut jwks = Vec::new();
+        let keys = std::iter::once((config.key_id.clone(), current_public_key))
+            .chain(config.previous_keys);
+        for (kid, key) in keys {
+            let mut verifier = match id {
+                AlgorithmID::HS256 => Algorithm::new_hmac(id, key)?,
+                AlgorithmID::ES256 => {
+                    jwks.push(public_key_to_jwk(id, &kid, &key)?);
+                    Algorithm::new_ecdsa_pem_verifier(id, &key)?
+                }
+                _ => {
+                    jwks.push(public_key_to_jwk(id, &kid, &key)?);
+                    Algorithm::new_rsa_pem_verifier(id, &key)?
+                }
+            };
+            verifier.set_kid(kid.clone());
+            verifying_keys.insert(kid, verifier);
+        }
 
         let verifier = Verifier::create().build()?;
 
         Ok(Self {
-            algorithm: alg,
-            expire_time,
+            algorithm,
+            key_id: config.key_id,
+            verifying_keys,
+            jwks: json!({ "keys": jwks }),
             verifier,
+            auth_expire_time: config.auth_expire_time,
+            refresh_expire_time: config.refresh_expire_time,
+            remember_refresh_expire_time: config.remember_refresh_expire_time,
         })
     }
 
+    /// 公开的验证密钥集合, HS256 时为空
+    pub fn jwks(&self) -> &Value {
+        &self.jwks
+    }
+
+    /// auth token 的有效期(秒)
+    pub fn auth_expire_time(&self) -> u64 {
+        self.auth_expire_time
+    }
+
     pub fn issue_auth_token(
         &self,
         subject: String,
         jwt_id: Option<String>,
+        role: String,
         authoritories: Vec<String>,
     ) -> MaaResult<SignedJwt> {
-        let now = chrono::Utc::now().timestamp();
-        let expire = now + self.expire_time as i64;
+        let now_millis = chrono::Utc::now().timestamp_millis();
+        let now = now_millis / 1000;
+        let expire = now + self.auth_expire_time as i64;
         let claims = JwtAuthClaims {
             sub: subject,
             jti: jwt_id,
+            role,
             auth: authoritories,
             iat: now,
+            iat_ms: Some(now_millis),
             exp: expire,
             nbf: now,
             typ: "auth".to_string(),
         };
 
-        let header = json!({
-            "alg": self.algorithm.name()
-        });
-
-        let token = jsonwebtokens::encode(&header, &claims, &self.algorithm)?;
+        let token = self.sign(&claims)?;
 
         Ok(SignedJwt {
             token,
@@ -96,10 +289,7 @@ impl JwtService {
         &self,
         auth_token: &str,
     ) -> MaaResult<JwtAuthClaims> {
-        let claims = self
-            .verifier
-            .verify(auth_token, &self.algorithm)
-            .map_err(|_| MaaError::JwtVerifyFailed)?;
+        let claims = self.verify(auth_token)?;
 
         serde_json::from_value(claims).map_err(|_| MaaError::JwtVerifyFailed)
     }
@@ -108,9 +298,15 @@ impl JwtService {
         &self,
         subject: String,
         jwt_id: Option<String>,
+        remember_me: bool,
     ) -> MaaResult<SignedJwt> {
         let now = chrono::Utc::now().timestamp();
-        let expire = now + self.expire_time as i64;
+        let expire_time = if remember_me {
+            self.remember_refresh_expire_time
+        } else {
+            self.refresh_expire_time
+        };
+        let expire = now + expire_time as i64;
         let claims = JwtRefreshClaims {
             sub: subject,
             jti: jwt_id,
@@ -120,11 +316,7 @@ impl JwtService {
             typ: "refresh".to_string(),
         };
 
-        let header = json!({
-            "alg": self.algorithm.name()
-        });
-
-        let token = jsonwebtokens::encode(&header, &claims, &self.algorithm)?;
+        let token = self.sign(&claims)?;
 
         Ok(SignedJwt {
             token,
@@ -148,11 +340,7 @@ impl JwtService {
             typ: "refresh".to_string(),
         };
 
-        let header = json!({
-            "alg": self.algorithm.name()
-        });
-
-        let token = jsonwebtokens::encode(&header, &claims, &self.algorithm)?;
+        let token = self.sign(&claims)?;
 
         Ok(SignedJwt {
             token,
@@ -165,11 +353,67 @@ impl JwtService {
         &self,
         refresh_token: &str,
     ) -> MaaResult<JwtRefreshClaims> {
-        let claims = self
-            .verifier
-            .verify(refresh_token, &self.algorithm)
-            .map_err(|_| MaaError::JwtVerifyFailed)?;
+        let claims = self.verify(refresh_token)?;
+
+        serde_json::from_value(claims).map_err(|_| MaaError::JwtVerifyFailed)
+    }
+
+    pub fn issue_two_factor_token(
+        &self,
+        subject: String,
+        remember_me: bool,
+    ) -> MaaResult<SignedJwt> {
+        let now = chrono::Utc::now().timestamp();
+        let claims = JwtTwoFactorClaims {
+            sub: subject,
+            remember_me,
+            iat: now,
+            exp: now + TWO_FACTOR_EXPIRE_TIME as i64,
+            nbf: now,
+            typ: "2fa".to_string(),
+        };
+
+        let token = self.sign(&claims)?;
+
+        Ok(SignedJwt {
+            token,
+            expires_at: claims.exp,
+            not_before: claims.nbf,
+        })
+    }
+
+    pub fn verify_and_parse_two_factor_token(
+        &self,
+        two_factor_token: &str,
+    ) -> MaaResult<JwtTwoFactorClaims> {
+        let claims = self.verify(two_factor_token)?;
 
         serde_json::from_value(claims).map_err(|_| MaaError::JwtVerifyFailed)
     }
+
+    fn sign<C: Serialize>(&self, claims: &C) -> MaaResult<String> {
+        let header = json!({
+            "alg": self.algorithm.name(),
+            "kid": self.key_id,
+            "typ": "JWT",
+        });
+
+        let token = jsonwebtokens::encode(&header, claims, &self.algorithm)?;
+        Ok(token)
+    }
+
+    /// 按照 header 中的 kid 选择验证密钥
+    fn verify(&self, token: &str) -> MaaResult<Value> {
+        let header =
+            decode_header_only(token).map_err(|_| MaaError::JwtVerifyFailed)?;
+        let algorithm = header
+            .get("kid")
+            .and_then(Value::as_str)
+            .and_then(|kid| self.verifying_keys.get(kid))
+            .ok_or(MaaError::JwtVerifyFailed)?;
+
+        self.verifier
+            .verify(token, algorithm)
+            .map_err(|_| MaaError::JwtVerifyFailed)
+    }
 }
@@ -0,0 +1,325 @@
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{BufReader, Read},
+    path::{Path, PathBuf},
+};
+
+use axum::async_trait;
+use flate2::read::GzDecoder;
+use git2::{ObjectType, Oid, Repository};
+
+use crate::{
+    repository::github_api::GithubApi, util::hash_util::git_blob_sha, MaaError,
+    MaaResult,
+};
+
+// 关卡文件在资源仓库中的目录
+pub const LEVEL_DIR: [&str; 2] = ["resource", "Arknights-Tile-Pos"];
+
+/// 关卡源中的一个关卡文件
+#[derive(Debug, Clone)]
+pub struct LevelFile {
+    // 文件名, 例: `level_main_01-07.json`
+    pub name: String,
+    // 文件内容的 git blob sha, 与 GitHub tree 中的 sha 一致, 用于判断文件是否变化
+    pub sha: String,
+    // 读取文件时使用的位置, 由各个关卡源自行解释
+    pub location: String,
+}
+
+/// 关卡数据的来源, 所有来源都提供与资源仓库相同的目录结构
+#[async_trait]
+pub trait LevelSource: Send + Sync {
+    /// 列出关卡目录下的所有关卡文件
+    async fn list_files(&self) -> MaaResult<Vec<LevelFile>>;
+
+    /// 读取一个关卡文件的内容
+    async fn read_file(&self, file: &LevelFile) -> MaaResult<String>;
+}
+
+/// 根据路径打开本地关卡源: 文件视为 tarball, git 仓库读取 `HEAD`, 其余视为目录
+///
+/// 会读取磁盘, 在异步上下文中应放到 `spawn_blocking` 中调用
+pub fn open_local_source(path: &Path) -> MaaResult<Box<dyn LevelSource>> {
+    if path.is_file() {
+        return Ok(Box::new(TarballLevelSource::open(path)?));
+    }
+    if Repository::open(path).is_ok() {
+        return Ok(Box::new(GitLevelSource::new(path, "HEAD")));
+    }
+    Ok(Box::new(DirectoryLevelSource::new(path)))
+}
+
+fn level_dir() -> PathBuf {
+    LEVEL_DIR.iter().collect()
+}
+
+fn is_level_file(name: &str) -> bool {
+    name.ends_with(".json")
+}
+
+/// 通过 GitHub API 读取资源仓库最新提交中的关卡文件
+pub struct GithubLevelSource {
+    github_api: GithubApi,
+}
+
+impl GithubLevelSource {
+    pub fn new(github_api: GithubApi) -> Self {
+        Self { github_api }
+    }
+}
+
+#[async_trait]
+impl LevelSource for GithubLevelSource {
+    /// 逐级进入最新提交的关卡目录, `location` 为 `{commit}/{path}`
+    async fn list_files(&self) -> MaaResult<Vec<LevelFile>> {
+        let commit = self
+            .github_api
+            .get_github_commits()
+            .await
+            .into_iter()
+            .next()
+            .ok_or_else(|| {
+                MaaError::LevelSyncError("failed to get commits".to_string())
+            })?;
+
+        let mut tree_sha = commit.sha.clone();
+        for dir in LEVEL_DIR {
+            let trees = self
+                .github_api
+                .get_github_trees(&tree_sha)
+                .await
+                .ok_or_else(|| {
+                    MaaError::LevelSyncError(format!(
+                        "failed to get tree {}",
+                        tree_sha
+                    ))
+                })?;
+            tree_sha = trees
+                .tree
+                .into_iter()
+                .find(|tree| tree.path == dir && tree.tree_type == "tree")
+                .map(|tree| tree.sha)
+                .ok_or_else(|| {
+                    MaaError::LevelSyncError(format!("missing dir {}", dir))
+                })?;
+        }
+
+        let trees = self
+            .github_api
+            .get_github_trees(&tree_sha)
+            .await
+            .ok_or_else(|| {
+                MaaError::LevelSyncError("failed to get level dir".into())
+            })?;
+        Ok(trees
+            .tree
+            .into_iter()
+            .filter(|tree| {
+                tree.tree_type == "blob" && is_level_file(&tree.path)
+            })
+            .map(|tree| LevelFile {
+                location: format!(
+                    "{}/{}/{}",
+                    commit.sha,
+                    LEVEL_DIR.join("/"),
+                    tree.path
+                ),
+                name: tree.path,
+                sha: tree.sha,
+            })
+            .collect())
+    }
+
+    async fn read_file(&self, file: &LevelFile) -> MaaResult<String> {
+        let (commit_sha, path) =
+            file.location.split_once('/').ok_or_else(|| {
+                MaaError::LevelSyncError(format!(
+                    "invalid location {}",
+                    file.location
+                ))
+            })?;
+        self.github_api.get_raw_file(commit_sha, path).await
+    }
+}
+
+/// 读取本地目录中的关卡文件, 目录可以是资源仓库根目录或关卡目录本身
+pub struct DirectoryLevelSource {
+    dir: PathBuf,
+}
+
+impl DirectoryLevelSource {
+    pub fn new(path: &Path) -> Self {
+        let nested = path.join(level_dir());
+        let dir = if nested.is_dir() {
+            nested
+        } else {
+            path.to_path_buf()
+        };
+        Self { dir }
+    }
+}
+
+#[async_trait]
+impl LevelSource for DirectoryLevelSource {
+    async fn list_files(&self) -> MaaResult<Vec<LevelFile>> {
+        let dir = self.dir.clone();
+        tokio::task::spawn_blocking(move || {
+            let mut files = Vec::new();
+            for entry in std::fs::read_dir(&dir)? {
+                let entry = entry?;
+                let name = entry.file_name().to_string_lossy().into_owned();
+                if entry.file_type()?.is_dir() || !is_level_file(&name) {
+                    continue;
+                }
+                let path = entry.path();
+                let content = std::fs::read(&path)?;
+                files.push(LevelFile {
+                    name,
+                    sha: git_blob_sha(&content)?,
+                    location: path.to_string_lossy().into_owned(),
+                });
+            }
+            Ok(files)
+        })
+        .await?
+    }
+
+    async fn read_file(&self, file: &LevelFile) -> MaaResult<String> {
+        let path = file.location.clone();
+        let content =
+            tokio::task::spawn_blocking(move || std::fs::read_to_string(path))
+                .await??;
+        Ok(content)
+    }
+}
+
+/// 读取本地 git 仓库中某个版本的关卡文件, 不受工作区未提交修改的影响
+pub struct GitLevelSource {
+    repo_path: PathBuf,
+    rev: String,
+}
+
+impl GitLevelSource {
+    pub fn new(repo_path: &Path, rev: &str) -> Self {
+        Self {
+            repo_path: repo_path.to_path_buf(),
+            rev: rev.to_string(),
+        }
+    }
+}
+
+#[async_trait]
+impl LevelSource for GitLevelSource {
+    /// `location` 为文件的 blob id
+    async fn list_files(&self) -> MaaResult<Vec<LevelFile>> {
+        let repo_path = self.repo_path.clone();
+        let rev = self.rev.clone();
+        tokio::task::spawn_blocking(move || {
+            let repo = Repository::open(repo_path)?;
+            let tree = repo.revparse_single(&rev)?.peel_to_tree()?;
+            let level_tree = tree
+                .get_path(&level_dir())?
+                .to_object(&repo)?
+                .peel_to_tree()?;
+            Ok(level_tree
+                .iter()
+                .filter(|entry| entry.kind() == Some(ObjectType::Blob))
+                .filter_map(|entry| {
+                    let name = entry.name()?.to_string();
+                    is_level_file(&name).then(|| LevelFile {
+                        name,
+                        sha: entry.id().to_string(),
+                        location: entry.id().to_string(),
+                    })
+                })
+                .collect())
+        })
+        .await?
+    }
+
+    async fn read_file(&self, file: &LevelFile) -> MaaResult<String> {
+        let repo_path = self.repo_path.clone();
+        let location = file.location.clone();
+        tokio::task::spawn_blocking(move || {
+            let repo = Repository::open(repo_path)?;
+            let blob = repo.find_blob(Oid::from_str(&location)?)?;
+            String::from_utf8(blob.content().to_vec()).map_err(|e| {
+                MaaError::LevelSyncError(format!("invalid utf-8: {}", e))
+            })
+        })
+        .await?
+    }
+}
+
+/// 读取资源仓库导出的 tarball(`.tar` 或 `.tar.gz`), 打开时将关卡文件读入内存
+pub struct TarballLevelSource {
+    files: Vec<LevelFile>,
+    contents: HashMap<String, String>,
+}
+
+impl TarballLevelSource {
+    /// 读取压缩包中所有位于 `Arknights-Tile-Pos` 目录下的关卡文件
+    pub fn open(path: &Path) -> MaaResult<Self> {
+        let mut reader = BufReader::new(File::open(path)?);
+        let mut magic = [0u8; 2];
+        let gzipped =
+            reader.read_exact(&mut magic).is_ok() && magic == [0x1f, 0x8b];
+        let reader = BufReader::new(File::open(path)?);
+        if gzipped {
+            Self::read_archive(GzDecoder::new(reader))
+        } else {
+            Self::read_archive(reader)
+        }
+    }
+
+    fn read_archive(reader: impl Read) -> MaaResult<Self> {
+        let level_dir_name = LEVEL_DIR.last().copied().unwrap_or_default();
+        let mut files = Vec::new();
+        let mut contents = HashMap::new();
+        let mut archive = tar::Archive::new(reader);
+        for entry in archive.entries()? {
+            let mut entry = entry?;
+            if !entry.header().entry_type().is_file() {
+                continue;
+            }
+            let path = entry.path()?.into_owned();
+            let in_level_dir = path
+                .parent()
+                .and_then(Path::file_name)
+                .is_some_and(|dir| dir == level_dir_name);
+            let Some(name) = path.file_name().and_then(|name| name.to_str())
+            else {
+                continue;
+            };
+            if !in_level_dir || !is_level_file(name) {
+                continue;
+            }
+
+            let mut content = String::new();
+            entry.read_to_string(&mut content)?;
+            let location = path.to_string_lossy().into_owned();
+            files.push(LevelFile {
+                name: name.to_string(),
+                sha: git_blob_sha(content.as_bytes())?,
+                location: location.clone(),
+            });
+            contents.insert(location, content);
+        }
+        Ok(Self { files, contents })
+    }
+}
+
+#[async_trait]
+impl LevelSource for TarballLevelSource {
+    async fn list_files(&self) -> MaaResult<Vec<LevelFile>> {
+        Ok(self.files.clone())
+    }
+
+    async fn read_file(&self, file: &LevelFile) -> MaaResult<String> {
+        self.contents.get(&file.location).cloned().ok_or_else(|| {
+            MaaError::LevelSyncError(format!("missing file {}", file.location))
+        })
+    }
+}
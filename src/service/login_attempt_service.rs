@@ -0,0 +1,141 @@
+use std::sync::Arc;
+
+use crate::{util::redis_cache::RedisCache, MaaError, MaaResult};
+
+use super::mail_service::MailService;
+
+// 登录失败计数的保留时间, 每次失败都会刷新
+const FAILURE_WINDOW: u64 = 60 * 60;
+// 同一邮箱连续失败多少次后开始锁定
+const EMAIL_FAILURE_THRESHOLD: u64 = 5;
+// 同一 IP 连续失败多少次后开始锁定, 需要容忍 NAT 后的多个用户
+const IP_FAILURE_THRESHOLD: u64 = 20;
+// 首次锁定的时长, 之后每失败一次翻倍
+const BASE_LOCK_SECONDS: u64 = 60;
+const MAX_LOCK_SECONDS: u64 = 24 * 60 * 60;
+
+/// 登录失败追踪, 按邮箱和 IP 分别计数, 失败次数过多时渐进式锁定
+pub struct LoginAttemptService {
+    redis_cache: Arc<RedisCache>,
+    mail_service: Arc<MailService>,
+}
+
+impl LoginAttemptService {
+    pub fn new(
+        redis_cache: Arc<RedisCache>,
+        mail_service: Arc<MailService>,
+    ) -> Self {
+        Self {
+            redis_cache,
+            mail_service,
+        }
+    }
+
+    /// 检查邮箱或 IP 是否处于锁定状态
+    pub async fn check(&self, email: &str, ip: &str) -> MaaResult<()> {
+        let email_lock = self.redis_cache.ttl(&email_lock_key(email)).await?;
+        let ip_lock = self.redis_cache.ttl(&ip_lock_key(ip)).await?;
+
+        match email_lock.max(ip_lock) {
+            Some(retry_after) if retry_after > 0 => {
+                Err(MaaError::LoginLocked { retry_after })
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// 记录一次登录失败, `notify` 为 `true` 时会在账户被锁定时发送提醒邮件
+    pub async fn record_failure(
+        &self,
+        email: &str,
+        ip: &str,
+        notify: bool,
+    ) -> MaaResult<()> {
+        let email_failures = self
+            .redis_cache
+            .incr_ex(&format!("LoginFailEmail:{}", email), FAILURE_WINDOW)
+            .await?;
+        if let Some(seconds) =
+            lock_seconds(email_failures, EMAIL_FAILURE_THRESHOLD)
+        {
+            tracing::warn!(
+                "Too many login failures for {}, locked for {}s",
+                email,
+                seconds
+            );
+            self.redis_cache
+                .set_ex(&email_lock_key(email), 1, seconds)
+                .await?;
+            if notify && email_failures == EMAIL_FAILURE_THRESHOLD {
+                self.send_alert(email, ip, seconds);
+            }
+        }
+
+        let ip_failures = self
+            .redis_cache
+            .incr_ex(&format!("LoginFailIp:{}", ip), FAILURE_WINDOW)
+            .await?;
+        if let Some(seconds) = lock_seconds(ip_failures, IP_FAILURE_THRESHOLD) {
+            tracing::warn!(
+                "Too many login failures from {}, locked for {}s",
+                ip,
+                seconds
+            );
+            self.redis_cache
+                .set_ex(&ip_lock_key(ip), 1, seconds)
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    /// 登录成功后清除该邮箱的失败计数
+    pub async fn record_success(&self, email: &str) -> MaaResult<()> {
+        self.redis_cache
+            .delete(&format!("LoginFailEmail:{}", email))
+            .await
+    }
+
+    fn send_alert(&self, email: &str, ip: &str, lock_seconds: u64) {
+        let mail_service = Arc::clone(&self.mail_service);
+        let email = email.to_string();
+        let ip = ip.to_string();
+        tokio::spawn(async move {
+            if let Err(e) = mail_service
+                .send_login_alert(&email, &ip, lock_seconds)
+                .await
+            {
+                tracing::warn!("Failed to send login alert: {}", e);
+            }
+        });
+    }
+}
+
+fn email_lock_key(email: &str) -> String {
+    format!("LoginLockEmail:{}", email)
+}
+
+fn ip_lock_key(ip: &str) -> String {
+    format!("LoginLockIp:{}", ip)
+}
+
+/// 根据失败次数计算锁定时长, 未达到阈值时返回 `None`
+fn lock_seconds(failures: u64, threshold: u64) -> Option<u64> {
+    let exceeded = failures.checked_sub(threshold)?;
+    let factor = 2u64.checked_pow(u32::try_from(exceeded).ok()?);
+    Some(
+        factor
+            .and_then(|factor| BASE_LOCK_SECONDS.checked_mul(factor))
+            .map_or(MAX_LOCK_SECONDS, |seconds| seconds.min(MAX_LOCK_SECONDS)),
+    )
+}
+
+#[test]
+fn t_lock_seconds() {
+    assert_eq!(lock_seconds(4, 5), None);
+    assert_eq!(lock_seconds(5, 5), Some(60));
+    assert_eq!(lock_seconds(6, 5), Some(120));
+    assert_eq!(lock_seconds(8, 5), Some(480));
+    assert_eq!(lock_seconds(30, 5), Some(MAX_LOCK_SECONDS));
+    assert_eq!(lock_seconds(200, 5), Some(MAX_LOCK_SECONDS));
+}
@@ -9,15 +9,65 @@ use crate::{
     envs::{
         mail_host, mail_password, mail_port, mail_username, vcode_expire_time,
     },
-    util::{handlebars_util::render_vcode_email, redis_cache::RedisCache},
+    util::{
+        handlebars_util::{
+            render_already_registered_email, render_login_alert_email,
+            render_vcode_email,
+        },
+        redis_cache::RedisCache,
+    },
     MaaError, MaaResult,
 };
 
 enum MailClient {
-    SmtpClient(Mutex<SmtpClient<TlsStream<TcpStream>>>),
+    SmtpClient(Box<Mutex<SmtpClient<TlsStream<TcpStream>>>>),
     MockClient,
 }
 
+/// 验证码的用途, 不同用途的验证码互不通用
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum VCodeKind {
+    Registration,
+    ResetPassword,
+    ChangeEmail,
+    Activation,
+}
+
+impl VCodeKind {
+    fn template(self) -> &'static str {
+        match self {
+            VCodeKind::Registration | VCodeKind::ChangeEmail => "vcode",
+            VCodeKind::ResetPassword => "reset-password",
+            VCodeKind::Activation => "activation",
+        }
+    }
+
+    fn subject(self) -> &'static str {
+        match self {
+            VCodeKind::Registration => "Maa Backend Center 验证码",
+            VCodeKind::ResetPassword => "Maa Backend Center 重置密码",
+            VCodeKind::ChangeEmail => "Maa Backend Center 更换邮箱",
+            VCodeKind::Activation => "Maa Backend Center 激活账户",
+        }
+    }
+
+    fn cache_key(self, email: &str) -> String {
+        match self {
+            VCodeKind::Registration => format!("vCodeEmail:{}", email),
+            VCodeKind::ResetPassword => {
+                format!("vCodeResetPassword:{}", email)
+            }
+            VCodeKind::ChangeEmail => format!("vCodeChangeEmail:{}", email),
+            VCodeKind::Activation => format!("vCodeActivation:{}", email),
+        }
+    }
+
+    /// 限制发送频率的 key, 不同用途的验证码分别限制
+    fn throttle_key(self, email: &str) -> String {
+        format!("HasBeenSentVCode:{}", self.cache_key(email))
+    }
+}
+
 pub struct MailService {
     mail_client: MailClient,
     redis_cache: Arc<RedisCache>,
@@ -46,7 +96,7 @@ impl MailService {
 
             match smtp_client {
                 Ok(smtp_client) => {
-                    MailClient::SmtpClient(Mutex::new(smtp_client))
+                    MailClient::SmtpClient(Box::new(Mutex::new(smtp_client)))
                 }
                 Err(e) => {
                     tracing::warn!("Failed to init mail service: {}", e);
@@ -63,23 +113,21 @@ impl MailService {
         })
     }
 
-    pub async fn send_vcode(&self, email: &str) -> MaaResult<()> {
-        // 一个过期周期最多重发十条，记录已发送的邮箱以及间隔时间
-        let timeout = self.vcode_expire / 10;
-
-        let exist = !self
-            .redis_cache
-            .set_if_not_exists_ex(
-                &format!("HasBeenSentVCode:{}", email),
-                timeout,
-                timeout,
-            )
-            .await?;
-
-        if exist {
-            return Err(MaaError::VCodeSentTooFrequently);
-        }
+    pub async fn send_vcode(
+        &self,
+        email: &str,
+        kind: VCodeKind,
+    ) -> MaaResult<()> {
+        self.throttle_vcode(email, kind).await?;
+        self.deliver_vcode(email, kind).await
+    }
 
+    /// 生成并发送验证码, 不检查发送频率, 调用前应先通过 `throttle_vcode` 记录发送
+    pub async fn deliver_vcode(
+        &self,
+        email: &str,
+        kind: VCodeKind,
+    ) -> MaaResult<()> {
         // generate random string of 6 digits
         let vcode = rand::thread_rng()
             .sample_iter(&Alphanumeric)
@@ -89,10 +137,10 @@ impl MailService {
 
         match &self.mail_client {
             MailClient::SmtpClient(client) => {
-                let mail_content = render_vcode_email(&vcode)?;
+                let mail_content = render_vcode_email(kind.template(), &vcode)?;
                 let mail = MessageBuilder::new()
                     .to(email)
-                    .subject("Maa Backend Center 验证码")
+                    .subject(kind.subject())
                     .html_body(&mail_content);
 
                 let mut mail_client = client.lock().await;
@@ -108,7 +156,7 @@ impl MailService {
 
         self.redis_cache
             .set_ex(
-                &format!("vCodeEmail:{}", email),
+                &kind.cache_key(email),
                 vcode.to_ascii_uppercase(),
                 self.vcode_expire,
             )
@@ -117,15 +165,101 @@ impl MailService {
         Ok(())
     }
 
+    /// 记录一次验证码发送, 距离上次发送间隔过短时返回 `VCodeSentTooFrequently`
+    pub async fn throttle_vcode(
+        &self,
+        email: &str,
+        kind: VCodeKind,
+    ) -> MaaResult<()> {
+        // 一个过期周期最多重发十条，记录已发送的邮箱以及间隔时间
+        let timeout = self.vcode_expire / 10;
+
+        let exist = !self
+            .redis_cache
+            .set_if_not_exists_ex(&kind.throttle_key(email), timeout, timeout)
+            .await?;
+
+        if exist {
+            return Err(MaaError::VCodeSentTooFrequently);
+        }
+        Ok(())
+    }
+
+    /// 提醒用户邮箱已注册, 在已注册的邮箱申请注册时代替验证码发送
+    pub async fn send_already_registered(&self, email: &str) -> MaaResult<()> {
+        match &self.mail_client {
+            MailClient::SmtpClient(client) => {
+                let mail_content = render_already_registered_email()?;
+                let mail = MessageBuilder::new()
+                    .to(email)
+                    .subject("Maa Backend Center 邮箱已注册")
+                    .html_body(&mail_content);
+
+                let mut mail_client = client.lock().await;
+                mail_client.send(mail).await?;
+            }
+            MailClient::MockClient => {
+                tracing::warn!(
+                    "Email not sent, no_send enabled, {} is already registered",
+                    email
+                );
+            }
+        };
+
+        Ok(())
+    }
+
+    /// 提醒用户账户因多次登录失败被暂时锁定
+    pub async fn send_login_alert(
+        &self,
+        email: &str,
+        ip: &str,
+        lock_seconds: u64,
+    ) -> MaaResult<()> {
+        let lock_minutes = lock_seconds.div_ceil(60);
+        match &self.mail_client {
+            MailClient::SmtpClient(client) => {
+                let mail_content = render_login_alert_email(ip, lock_minutes)?;
+                let mail = MessageBuilder::new()
+                    .to(email)
+                    .subject("Maa Backend Center 可疑的登录尝试")
+                    .html_body(&mail_content);
+
+                let mut mail_client = client.lock().await;
+                mail_client.send(mail).await?;
+            }
+            MailClient::MockClient => {
+                tracing::warn!(
+                    "Email not sent, no_send enabled, login alert for {} from {}",
+                    email,
+                    ip
+                );
+            }
+        };
+
+        Ok(())
+    }
+
+    /// 距离下一次允许向该邮箱发送该用途验证码的剩余秒数
+    pub async fn vcode_retry_after(
+        &self,
+        email: &str,
+        kind: VCodeKind,
+    ) -> MaaResult<u64> {
+        let ttl = self.redis_cache.ttl(&kind.throttle_key(email)).await?;
+        Ok(ttl.unwrap_or(0))
+    }
+
     pub async fn verify_vcode(
         &self,
         email: &str,
         vcode: &str,
+        kind: VCodeKind,
     ) -> MaaResult<()> {
         let result = self
             .redis_cache
             .delete_if_equals(
-                &format!("vCodeEmail:{}", email),
+                &kind.cache_key(email),
                 vcode.to_ascii_uppercase().to_string(),
             )
             .await?;
@@ -137,3 +271,17 @@ impl MailService {
         Ok(())
     }
 }
+
+#[test]
+fn t_vcode_throttle_key_per_kind() {
+    let email = "user@example.com";
+    let kinds = [
+        VCodeKind::Registration,
+        VCodeKind::ResetPassword,
+        VCodeKind::ChangeEmail,
+        VCodeKind::Activation,
+    ];
+    let keys: std::collections::HashSet<String> =
+        kinds.iter().map(|kind| kind.throttle_key(email)).collect();
+    assert_eq!(keys.len(), kinds.len());
+}
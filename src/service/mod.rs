@@ -1,3 +1,6 @@
+pub mod ark_level_sync_service;
 pub mod jwt_service;
+pub mod level_source;
+pub mod login_attempt_service;
 pub mod mail_service;
 pub mod user_service;
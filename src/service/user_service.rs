@@ -1,59 +1,140 @@
-use std::sync::Arc;
+use std::{future::Future, sync::Arc};
 
+use rand::{distributions::Alphanumeric, Rng};
 use uuid::Uuid;
 use validator::Validate;
 
 use crate::{
-    envs::max_login_count,
-    repository::user_repository::{MaaUser, UserRepository},
+    envs::{max_login_count, registration_mode},
+    repository::{
+        user_repository::{MaaUser, MaaUserSession, UserRepository},
+        user_token_repository::{MaaUserToken, UserTokenRepository},
+    },
     route::{
+        extract::ClientInfo,
+        request::admin::{
+            AdminUserQuery, AssignRoleRequest, UpdateUserStatusRequest,
+        },
         request::user::{
-            LoginRequest, RegisterRequest, SendRegistrationTokenRequest,
+            ActivateAccountRequest, ChangeEmailRequest,
+            ChangeEmailVCodeRequest, CreatePersonalTokenRequest, LoginRequest,
+            LogoutRequest, PasswordResetRequest, PasswordResetVCodeRequest,
+            RefreshTokenRequest, RegisterRequest, ResendActivationRequest,
+            SendRegistrationTokenRequest, TwoFactorCodeRequest,
+            TwoFactorLoginRequest, UpdatePasswordRequest,
+            UpdateUserInfoRequest, UserSearchQuery,
         },
-        response::user::{MaaLoginResponse, MaaUserInfo},
+        response::admin::{MaaAdminUserInfo, MaaAdminUserPage},
+        response::user::{
+            MaaLoginResponse, MaaLoginResult, MaaPersonalTokenCreated,
+            MaaPersonalTokenInfo, MaaRegisterResult, MaaSessionInfo,
+            MaaTwoFactorEnrollment, MaaTwoFactorPending, MaaUserInfo,
+            MaaUserProfile, SendVCodeResponse,
+        },
+    },
+    util::{
+        hash_util::sha256_hex, password_encoder::PasswordEncoder,
+        permission::MaaRole, redis_cache::RedisCache, totp_util,
     },
-    util::password_encoder::PasswordEncoder,
     MaaError, MaaResult,
 };
 
-use super::{jwt_service::JwtService, mail_service::MailService};
+use super::{
+    jwt_service::{JwtAuthClaims, JwtService},
+    login_attempt_service::LoginAttemptService,
+    mail_service::{MailService, VCodeKind},
+};
+
+// 个人访问令牌的前缀, 用于和 JWT 区分
+const PERSONAL_TOKEN_PREFIX: &str = "maa_pat_";
+const PERSONAL_TOKEN_LEN: usize = 40;
+// 每个用户最多拥有的个人访问令牌数量
+const MAX_PERSONAL_TOKENS: u64 = 20;
+// 令牌最后使用时间的更新间隔(毫秒), 避免每个请求都写数据库
+const PERSONAL_TOKEN_TOUCH_INTERVAL: i64 = 60 * 1000;
+
+// 用户名搜索最多返回的数量
+const USER_SEARCH_LIMIT: i64 = 20;
+
+/// 新用户的注册方式
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RegistrationMode {
+    /// 注册前需要提交邮箱验证码, 注册后账户直接启用
+    VCode,
+    /// 注册时无需验证码, 账户在通过邮件中的激活码激活后才能登录
+    Activation,
+}
+
+impl RegistrationMode {
+    fn from_env() -> MaaResult<Self> {
+        match registration_mode().as_deref() {
+            Err(_) | Ok("vcode") => Ok(RegistrationMode::VCode),
+            Ok("activation") => Ok(RegistrationMode::Activation),
+            Ok(other) => Err(MaaError::ConfigError(format!(
+                "unsupported registration mode: {}",
+                other
+            ))),
+        }
+    }
+}
 
 pub struct UserService {
     user_repository: UserRepository,
+    user_token_repository: UserTokenRepository,
     password_encoder: PasswordEncoder,
     mail_service: Arc<MailService>,
     jwt_service: Arc<JwtService>,
+    redis_cache: Arc<RedisCache>,
+    login_attempts: LoginAttemptService,
     max_login: usize,
+    registration_mode: RegistrationMode,
 }
 
 impl UserService {
     pub fn new(
         user_repository: UserRepository,
+        user_token_repository: UserTokenRepository,
         jwt_service: Arc<JwtService>,
         mail_service: Arc<MailService>,
-    ) -> Self {
-        let password_encoder = PasswordEncoder::new();
+        redis_cache: Arc<RedisCache>,
+    ) -> MaaResult<Self> {
+        let password_encoder = PasswordEncoder::from_env()?;
         let max_login = max_login_count().unwrap_or(1);
-        Self {
+        let registration_mode = RegistrationMode::from_env()?;
+        let login_attempts = LoginAttemptService::new(
+            Arc::clone(&redis_cache),
+            Arc::clone(&mail_service),
+        );
+        Ok(Self {
             user_repository,
+            user_token_repository,
             password_encoder,
+            login_attempts,
             max_login,
+            registration_mode,
             mail_service,
             jwt_service,
-        }
+            redis_cache,
+        })
     }
 
     pub async fn login(
         &self,
         req: LoginRequest,
-    ) -> MaaResult<MaaLoginResponse> {
+        client: ClientInfo,
+    ) -> MaaResult<MaaLoginResult> {
         req.validate()?;
 
-        let mut user = self
-            .user_repository
-            .find_by_email(&req.email)
-            .await?
-            .ok_or(MaaError::LoginFail)?;
+        self.login_attempts.check(&req.email, &client.ip).await?;
+
+        let Some(mut user) =
+            self.user_repository.find_by_email(&req.email).await?
+        else {
+            self.login_attempts
+                .record_failure(&req.email, &client.ip, false)
+                .await?;
+            return Err(MaaError::LoginFail);
+        };
 
         let user_id = match user.user_id {
             Some(ref id) => id.clone(),
@@ -62,8 +143,12 @@ impl UserService {
 
         if !self
             .password_encoder
-            .matches(&req.password, &user.password)?
+            .matches(&req.password, &user.password)
+            .await?
         {
+            self.login_attempts
+                .record_failure(&req.email, &client.ip, true)
+                .await?;
             return Err(MaaError::LoginFail);
         }
 
@@ -71,78 +156,1104 @@ impl UserService {
             return Err(MaaError::UserNotEnabled);
         }
 
+        // 旧算法或旧参数生成的哈希, 趁着明文密码可用时重新哈希
+        if self.password_encoder.needs_rehash(&user.password) {
+            let encoded = self.password_encoder.encode(&req.password).await?;
+            self.user_repository
+                .update_password(&user_id, &encoded)
+                .await?;
+            user.password = encoded;
+        }
+
+        // 两步验证通过前不清除失败计数, 避免借助密码登录重置验证码的尝试次数
+        if user.totp_enabled {
+            let token = self
+                .jwt_service
+                .issue_two_factor_token(user_id, req.remember_me)?;
+            return Ok(MaaLoginResult::TwoFactorRequired(
+                MaaTwoFactorPending {
+                    two_factor_required: true,
+                    two_factor_token: token.token,
+                    valid_before: token.expires_at,
+                },
+            ));
+        }
+
+        self.login_attempts.record_success(&req.email).await?;
+
+        self.issue_login(user, client, req.remember_me)
+            .await
+            .map(MaaLoginResult::Success)
+    }
+
+    /// 提交两步验证码以完成登录
+    pub async fn login_two_factor(
+        &self,
+        req: TwoFactorLoginRequest,
+        client: ClientInfo,
+    ) -> MaaResult<MaaLoginResponse> {
+        req.validate()?;
+
+        let claims = self
+            .jwt_service
+            .verify_and_parse_two_factor_token(&req.two_factor_token)?;
+        if claims.typ != "2fa" {
+            return Err(MaaError::JwtVerifyFailed);
+        }
+
+        let user = self
+            .user_repository
+            .find_by_user_id(&claims.sub)
+            .await?
+            .ok_or(MaaError::JwtVerifyFailed)?;
+        if !user.totp_enabled {
+            return Err(MaaError::JwtVerifyFailed);
+        }
+        if user.status == 0 {
+            return Err(MaaError::UserNotEnabled);
+        }
+
+        self.login_attempts.check(&user.email, &client.ip).await?;
+        if !self.check_two_factor_code(&user, &req.code).await? {
+            self.login_attempts
+                .record_failure(&user.email, &client.ip, true)
+                .await?;
+            return Err(MaaError::TwoFactorCodeNotMatch);
+        }
+        self.login_attempts.record_success(&user.email).await?;
+
+        self.issue_login(user, client, claims.remember_me).await
+    }
+
+    /// 创建会话并签发 auth token 以及 refresh token
+    async fn issue_login(
+        &self,
+        user: MaaUser,
+        client: ClientInfo,
+        remember_me: bool,
+    ) -> MaaResult<MaaLoginResponse> {
+        let user_id = user.user_id.clone().ok_or(MaaError::NoneUserId)?;
+
         let jwt_id = Uuid::new_v4().to_string();
-        user.refresh_jwt_ids.push(jwt_id.clone());
-        while user.refresh_jwt_ids.len() > self.max_login {
-            user.refresh_jwt_ids.remove(0);
+        let session = MaaUserSession {
+            jti: jwt_id.clone(),
+            issued_at: chrono::Utc::now().timestamp(),
+            ip: client.ip,
+            user_agent: client.user_agent,
+        };
+
+        let auth_token = self.jwt_service.issue_auth_token(
+            user_id.clone(),
+            Some(Uuid::new_v4().to_string()),
+            user.role.name().to_string(),
+            authorities(&user),
+        )?;
+
+        let refresh_token = self.jwt_service.issue_refresh_token(
+            user_id.clone(),
+            Some(jwt_id),
+            remember_me,
+        )?;
+
+        self.user_repository
+            .add_session(&user_id, &session, self.max_login)
+            .await?;
+
+        let resp = MaaLoginResponse {
+            token: auth_token.token,
+            valid_before: auth_token.expires_at,
+            valid_after: auth_token.not_before,
+            refresh_token: refresh_token.token,
+            refresh_token_valid_before: refresh_token.expires_at,
+            refresh_token_valid_after: refresh_token.not_before,
+            user_info: user.into(),
+        };
+
+        Ok(resp)
+    }
+
+    pub async fn refresh_token(
+        &self,
+        req: RefreshTokenRequest,
+    ) -> MaaResult<MaaLoginResponse> {
+        req.validate()?;
+
+        let claims = self
+            .jwt_service
+            .verify_and_parse_refresh_token(&req.refresh_token)?;
+        if claims.typ != "refresh" {
+            return Err(MaaError::JwtVerifyFailed);
         }
+        let jwt_id = claims.jti.clone().ok_or(MaaError::JwtVerifyFailed)?;
 
-        let authorities: Vec<String> =
-            (0..user.status).map(|i| i.to_string()).collect();
+        let user = self
+            .user_repository
+            .find_by_user_id(&claims.sub)
+            .await?
+            .ok_or(MaaError::JwtVerifyFailed)?;
+
+        let user_id = claims.sub.clone();
+
+        if user.status == 0 {
+            return Err(MaaError::UserNotEnabled);
+        }
+
+        // refresh token 只能使用一次，已被轮换的 token 再次出现说明可能已泄露，
+        // 此时吊销该用户的所有会话。轮换在数据库中原子地完成，
+        // 并发使用同一个 token 时只有一个请求能成功
+        let new_jwt_id = Uuid::new_v4().to_string();
+        if !self
+            .user_repository
+            .rotate_session(&user_id, &jwt_id, &new_jwt_id)
+            .await?
+        {
+            tracing::warn!(
+                "Refresh token reused for user {}, revoking all sessions",
+                user_id
+            );
+            self.user_repository.clear_sessions(&user_id).await?;
+            self.revoke_all_auth_tokens(&user_id).await?;
+            return Err(MaaError::JwtVerifyFailed);
+        }
 
         let auth_token = self.jwt_service.issue_auth_token(
             user_id.clone(),
-            None,
-            authorities,
+            Some(Uuid::new_v4().to_string()),
+            user.role.name().to_string(),
+            authorities(&user),
         )?;
 
         let refresh_token = self
             .jwt_service
-            .issue_refresh_token(user_id, Some(jwt_id))?;
+            .new_refresh_token(claims, Some(new_jwt_id))?;
 
         let resp = MaaLoginResponse {
             token: auth_token.token,
             valid_before: auth_token.expires_at,
             valid_after: auth_token.not_before,
             refresh_token: refresh_token.token,
-            refresh_token_valid_before: auth_token.expires_at,
-            refresh_token_valid_after: auth_token.not_before,
+            refresh_token_valid_before: refresh_token.expires_at,
+            refresh_token_valid_after: refresh_token.not_before,
             user_info: user.into(),
         };
 
         Ok(resp)
     }
 
+    /// 退出登录, 移除 refresh token 对应的会话并吊销当前的 auth token
+    pub async fn logout(
+        &self,
+        user: MaaUser,
+        auth_claims: &JwtAuthClaims,
+        req: LogoutRequest,
+    ) -> MaaResult<()> {
+        req.validate()?;
+
+        self.revoke_auth_token(auth_claims).await?;
+
+        let user_id = user.user_id.clone().ok_or(MaaError::NoneUserId)?;
+
+        let claims = self
+            .jwt_service
+            .verify_and_parse_refresh_token(&req.refresh_token)?;
+        if claims.typ != "refresh" || claims.sub != user_id {
+            return Err(MaaError::JwtVerifyFailed);
+        }
+        let jwt_id = claims.jti.ok_or(MaaError::JwtVerifyFailed)?;
+
+        self.user_repository
+            .remove_session(&user_id, &jwt_id)
+            .await?;
+
+        Ok(())
+    }
+
+    pub fn list_sessions(&self, user: &MaaUser) -> Vec<MaaSessionInfo> {
+        user.refresh_jwt_ids
+            .iter()
+            .map(|jti| {
+                let session = user.sessions.iter().find(|s| s.jti == *jti);
+                MaaSessionInfo {
+                    id: jti.clone(),
+                    issued_at: session.map(|s| s.issued_at),
+                    ip: session.map(|s| s.ip.clone()),
+                    user_agent: session.map(|s| s.user_agent.clone()),
+                }
+            })
+            .collect()
+    }
+
+    pub async fn revoke_session(
+        &self,
+        user: MaaUser,
+        session_id: &str,
+    ) -> MaaResult<()> {
+        let user_id = user.user_id.ok_or(MaaError::NoneUserId)?;
+
+        if !self
+            .user_repository
+            .remove_session(&user_id, session_id)
+            .await?
+        {
+            return Err(MaaError::SessionNotFound);
+        }
+
+        Ok(())
+    }
+
+    /// 校验 auth token 并加载对应用户
+    ///
+    /// token 中携带的权限必须仍被用户当前的状态所允许，
+    /// 这样在用户被禁用或降级后，旧 token 会立即失效
+    pub async fn authenticate(
+        &self,
+        auth_token: &str,
+    ) -> MaaResult<(MaaUser, JwtAuthClaims)> {
+        if auth_token.starts_with(PERSONAL_TOKEN_PREFIX) {
+            return self.authenticate_personal_token(auth_token).await;
+        }
+
+        let claims =
+            self.jwt_service.verify_and_parse_auth_token(auth_token)?;
+        if claims.typ != "auth" {
+            return Err(MaaError::JwtVerifyFailed);
+        }
+
+        self.check_auth_token_revoked(&claims).await?;
+
+        let user = self
+            .user_repository
+            .find_by_user_id(&claims.sub)
+            .await?
+            .ok_or(MaaError::JwtVerifyFailed)?;
+
+        if user.status == 0 {
+            return Err(MaaError::UserNotEnabled);
+        }
+
+        // 角色变更后旧 token 失效, 客户端刷新 token 即可获得新的权限
+        if claims
+            .auth
+            .iter()
+            .any(|permission| !user.role.has_permission(permission))
+        {
+            return Err(MaaError::JwtVerifyFailed);
+        }
+
+        Ok((user, claims))
+    }
+
+    /// 校验个人访问令牌, 返回的 claims 中的权限为令牌权限与用户当前角色权限的交集
+    async fn authenticate_personal_token(
+        &self,
+        token: &str,
+    ) -> MaaResult<(MaaUser, JwtAuthClaims)> {
+        let token = self
+            .user_token_repository
+            .find_by_hash(&sha256_hex(token.as_bytes()))
+            .await?
+            .filter(|token| !token.is_expired())
+            .ok_or(MaaError::JwtVerifyFailed)?;
+        let token_id = token.token_id.ok_or(MaaError::JwtVerifyFailed)?;
+
+        let user = self
+            .user_repository
+            .find_by_user_id(&token.user_id)
+            .await?
+            .ok_or(MaaError::JwtVerifyFailed)?;
+
+        if user.status == 0 {
+            return Err(MaaError::UserNotEnabled);
+        }
+
+        let now = bson::DateTime::now();
+        if token.last_used_time.is_none_or(|time| {
+            now.timestamp_millis() - time.timestamp_millis()
+                > PERSONAL_TOKEN_TOUCH_INTERVAL
+        }) {
+            self.user_token_repository
+                .update_last_used_time(&token_id)
+                .await?;
+        }
+
+        let claims = JwtAuthClaims {
+            sub: token.user_id,
+            // 个人访问令牌只能通过删除来吊销
+            jti: None,
+            role: user.role.name().to_string(),
+            auth: token
+                .scopes
+                .into_iter()
+                .filter(|scope| user.role.has_permission(scope))
+                .collect(),
+            iat: token.create_time.timestamp_millis() / 1000,
+            iat_ms: Some(token.create_time.timestamp_millis()),
+            exp: token
+                .expire_time
+                .map_or(i64::MAX, |time| time.timestamp_millis() / 1000),
+            nbf: token.create_time.timestamp_millis() / 1000,
+            typ: "pat".to_string(),
+        };
+
+        Ok((user, claims))
+    }
+
+    pub async fn list_personal_tokens(
+        &self,
+        user: &MaaUser,
+    ) -> MaaResult<Vec<MaaPersonalTokenInfo>> {
+        let user_id = user.user_id.as_deref().ok_or(MaaError::NoneUserId)?;
+        let tokens =
+            self.user_token_repository.find_by_user_id(user_id).await?;
+        Ok(tokens.into_iter().map(Into::into).collect())
+    }
+
+    /// 创建个人访问令牌, 令牌的权限不能超出用户当前角色的权限
+    pub async fn create_personal_token(
+        &self,
+        user: &MaaUser,
+        req: CreatePersonalTokenRequest,
+    ) -> MaaResult<MaaPersonalTokenCreated> {
+        req.validate()?;
+
+        let user_id = user.user_id.clone().ok_or(MaaError::NoneUserId)?;
+        if !req
+            .scopes
+            .iter()
+            .all(|scope| user.role.has_permission(scope))
+        {
+            return Err(MaaError::PermissionDenied);
+        }
+        if self
+            .user_token_repository
+            .count_by_user_id(&user_id)
+            .await?
+            >= MAX_PERSONAL_TOKENS
+        {
+            return Err(MaaError::TooManyPersonalTokens);
+        }
+
+        let mut scopes = req.scopes;
+        scopes.sort();
+        scopes.dedup();
+
+        let secret: String = rand::thread_rng()
+            .sample_iter(&Alphanumeric)
+            .take(PERSONAL_TOKEN_LEN)
+            .map(char::from)
+            .collect();
+        let token = format!("{}{}", PERSONAL_TOKEN_PREFIX, secret);
+
+        let now = bson::DateTime::now();
+        let expire_time = req.expires_in_days.map(|days| {
+            bson::DateTime::from_millis(
+                now.timestamp_millis() + i64::from(days) * 24 * 60 * 60 * 1000,
+            )
+        });
+
+        let saved = self
+            .user_token_repository
+            .save(MaaUserToken {
+                token_id: None,
+                user_id,
+                name: req.name,
+                token_hash: sha256_hex(token.as_bytes()),
+                scopes,
+                create_time: now,
+                expire_time,
+                last_used_time: None,
+            })
+            .await?;
+
+        Ok(MaaPersonalTokenCreated {
+            token,
+            info: saved.into(),
+        })
+    }
+
+    pub async fn revoke_personal_token(
+        &self,
+        user: &MaaUser,
+        token_id: &str,
+    ) -> MaaResult<()> {
+        let user_id = user.user_id.as_deref().ok_or(MaaError::NoneUserId)?;
+        if !self.user_token_repository.delete(user_id, token_id).await? {
+            return Err(MaaError::PersonalTokenNotFound);
+        }
+        Ok(())
+    }
+
+    /// 注册账户
+    ///
+    /// 通过邮件激活时, 不论邮箱是否已注册都执行相同的操作并返回相同的结果,
+    /// 已注册的邮箱会收到提醒邮件而不是激活码
     pub async fn register(
         &self,
         req: RegisterRequest,
-    ) -> MaaResult<MaaUserInfo> {
+    ) -> MaaResult<MaaRegisterResult> {
         req.validate()?;
 
-        self.mail_service
-            .verify_vcode(&req.email, &req.registration_token)
-            .await?;
+        let activation = self.registration_mode == RegistrationMode::Activation;
+        if !activation {
+            let vcode = req
+                .registration_token
+                .as_deref()
+                .ok_or(MaaError::VCodeNotMatch)?;
+            self.mail_service
+                .verify_vcode(&req.email, vcode, VCodeKind::Registration)
+                .await?;
+        }
 
-        let encoded = self.password_encoder.encode(&req.password)?;
+        let encoded = self.password_encoder.encode(&req.password).await?;
 
         let user = MaaUser {
             user_id: None,
             user_name: req.user_name,
             email: req.email,
             password: encoded,
-            status: 1,
+            status: if activation { 0 } else { 1 },
+            activation_pending: activation,
+            role: MaaRole::default(),
             refresh_jwt_ids: vec![],
+            sessions: vec![],
+            create_time: Some(bson::DateTime::now()),
+            totp_secret: None,
+            totp_enabled: false,
+            recovery_codes: vec![],
+            totp_last_step: None,
         };
 
-        self.user_repository.save(user.clone()).await?;
+        if !activation {
+            let user = self.user_repository.save(user).await?;
+            return Ok(MaaRegisterResult::Registered(user.into()));
+        }
 
-        Ok(user.into())
+        let email = user.email.clone();
+        let registered = match self.user_repository.save(user).await {
+            Ok(_) => false,
+            Err(MaaError::RegistrationUserExist) => true,
+            Err(e) => return Err(e),
+        };
+        // 邮件发送失败时用户可以重新发送激活码, 不影响注册结果
+        let sent = self.throttle_vcode(&email, VCodeKind::Activation).await?;
+        if sent {
+            let mail_service = Arc::clone(&self.mail_service);
+            let email = email.clone();
+            spawn_mail(async move {
+                if registered {
+                    mail_service.send_already_registered(&email).await
+                } else {
+                    mail_service
+                        .deliver_vcode(&email, VCodeKind::Activation)
+                        .await
+                }
+            });
+        }
+        self.vcode_response(&email, VCodeKind::Activation, sent)
+            .await
+            .map(MaaRegisterResult::ActivationRequired)
+    }
+
+    /// 使用邮件中的激活码激活账户
+    ///
+    /// 只有等待激活的账户才会收到激活码, 未注册或已激活的邮箱与激活码错误返回相同的结果
+    pub async fn activate(&self, req: ActivateAccountRequest) -> MaaResult<()> {
+        req.validate()?;
+
+        self.mail_service
+            .verify_vcode(
+                &req.email,
+                &req.activation_code,
+                VCodeKind::Activation,
+            )
+            .await?;
+
+        let user_id = self
+            .user_repository
+            .find_by_email(&req.email)
+            .await?
+            .and_then(|user| user.user_id)
+            .ok_or(MaaError::VCodeNotMatch)?;
+
+        if !self.user_repository.activate(&user_id).await? {
+            return Err(MaaError::UserAlreadyActivated);
+        }
+        Ok(())
+    }
+
+    pub async fn resend_activation_code(
+        &self,
+        req: ResendActivationRequest,
+    ) -> MaaResult<SendVCodeResponse> {
+        req.validate()?;
+
+        // 邮箱未注册或账户已激活时不发送邮件
+        self.send_vcode(&req.email, VCodeKind::Activation).await
     }
 
     pub async fn send_registration_token(
         &self,
         req: SendRegistrationTokenRequest,
+    ) -> MaaResult<SendVCodeResponse> {
+        req.validate()?;
+
+        // 已注册的邮箱收到提醒邮件而不是验证码
+        self.send_vcode(&req.email, VCodeKind::Registration).await
+    }
+
+    pub async fn send_password_reset_vcode(
+        &self,
+        req: PasswordResetVCodeRequest,
+    ) -> MaaResult<SendVCodeResponse> {
+        req.validate()?;
+
+        // 邮箱未注册时不发送邮件
+        self.send_vcode(&req.email, VCodeKind::ResetPassword).await
+    }
+
+    /// 通过邮箱验证码重置密码, 成功后该用户的所有会话以及个人访问令牌都会失效
+    pub async fn reset_password(
+        &self,
+        req: PasswordResetRequest,
+    ) -> MaaResult<()> {
+        req.validate()?;
+
+        // 未注册的邮箱不会有验证码, 与验证码错误返回相同的结果
+        let user = self
+            .user_repository
+            .find_by_email(&req.email)
+            .await?
+            .ok_or(MaaError::VCodeNotMatch)?;
+        let user_id = user.user_id.ok_or(MaaError::NoneUserId)?;
+
+        self.mail_service
+            .verify_vcode(
+                &req.email,
+                &req.active_code,
+                VCodeKind::ResetPassword,
+            )
+            .await?;
+
+        let encoded = self.password_encoder.encode(&req.password).await?;
+
+        self.user_repository
+            .update_password(&user_id, &encoded)
+            .await?;
+        self.user_repository.clear_sessions(&user_id).await?;
+        self.revoke_all_auth_tokens(&user_id).await?;
+        self.user_token_repository
+            .delete_by_user_id(&user_id)
+            .await?;
+
+        Ok(())
+    }
+
+    /// 修改密码, 需要提供原密码, 成功后该用户的所有会话都会失效
+    pub async fn update_password(
+        &self,
+        user: &MaaUser,
+        req: UpdatePasswordRequest,
     ) -> MaaResult<()> {
         req.validate()?;
 
-        let user = self.user_repository.find_by_email(&req.email).await?;
+        let user_id = user.user_id.as_deref().ok_or(MaaError::NoneUserId)?;
+
+        if !self
+            .password_encoder
+            .matches(&req.original_password, &user.password)
+            .await?
+        {
+            return Err(MaaError::LoginFail);
+        }
+
+        let encoded = self.password_encoder.encode(&req.new_password).await?;
+
+        self.user_repository
+            .update_password(user_id, &encoded)
+            .await?;
+        self.user_repository.clear_sessions(user_id).await?;
+        self.revoke_all_auth_tokens(user_id).await?;
+
+        Ok(())
+    }
+
+    pub async fn update_user_info(
+        &self,
+        user: MaaUser,
+        req: UpdateUserInfoRequest,
+    ) -> MaaResult<MaaUserInfo> {
+        req.validate()?;
+
+        let user_id = user.user_id.as_deref().ok_or(MaaError::NoneUserId)?;
+
+        self.user_repository
+            .update_user_name(user_id, &req.user_name)
+            .await?;
+
+        Ok(MaaUser {
+            user_name: req.user_name,
+            ..user
+        }
+        .into())
+    }
+
+    /// 向新邮箱发送验证码, 用于更换邮箱
+    pub async fn send_change_email_vcode(
+        &self,
+        req: ChangeEmailVCodeRequest,
+    ) -> MaaResult<SendVCodeResponse> {
+        req.validate()?;
+
+        if self
+            .user_repository
+            .find_by_email(&req.email)
+            .await?
+            .is_some()
+        {
+            return Err(MaaError::EmailAlreadyInUse);
+        }
+
+        self.send_vcode(&req.email, VCodeKind::ChangeEmail).await
+    }
+
+    pub async fn change_email(
+        &self,
+        user: &MaaUser,
+        req: ChangeEmailRequest,
+    ) -> MaaResult<()> {
+        req.validate()?;
+
+        let user_id = user.user_id.as_deref().ok_or(MaaError::NoneUserId)?;
+
+        self.mail_service
+            .verify_vcode(&req.email, &req.vcode, VCodeKind::ChangeEmail)
+            .await?;
+
+        if self
+            .user_repository
+            .find_by_email(&req.email)
+            .await?
+            .is_some()
+        {
+            return Err(MaaError::EmailAlreadyInUse);
+        }
+
+        self.user_repository
+            .update_email(user_id, &req.email)
+            .await?;
+
+        Ok(())
+    }
+
+    /// 生成新的 TOTP 密钥以及恢复码, 需要调用 `confirm_two_factor` 确认后生效
+    pub async fn enroll_two_factor(
+        &self,
+        user: &MaaUser,
+    ) -> MaaResult<MaaTwoFactorEnrollment> {
+        let user_id = user.user_id.as_deref().ok_or(MaaError::NoneUserId)?;
+        if user.totp_enabled {
+            return Err(MaaError::TwoFactorAlreadyEnabled);
+        }
+
+        let secret = totp_util::generate_secret();
+        let otpauth_uri = totp_util::otpauth_uri(&secret, &user.email)?;
+        let recovery_codes = totp_util::generate_recovery_codes();
+        let hashes: Vec<String> = recovery_codes
+            .iter()
+            .map(|code| totp_util::hash_recovery_code(code))
+            .collect();
+
+        self.user_repository
+            .update_two_factor(user_id, Some(&secret), &hashes)
+            .await?;
+
+        Ok(MaaTwoFactorEnrollment {
+            secret,
+            otpauth_uri,
+            recovery_codes,
+        })
+    }
+
+    /// 使用验证器应用生成的验证码确认开启两步验证
+    pub async fn confirm_two_factor(
+        &self,
+        user: &MaaUser,
+        req: TwoFactorCodeRequest,
+    ) -> MaaResult<()> {
+        req.validate()?;
+
+        let user_id = user.user_id.as_deref().ok_or(MaaError::NoneUserId)?;
+        if user.totp_enabled {
+            return Err(MaaError::TwoFactorAlreadyEnabled);
+        }
+        let secret = user
+            .totp_secret
+            .as_deref()
+            .ok_or(MaaError::TwoFactorNotEnabled)?;
+        let step = totp_util::verify_code(secret, &req.code)?
+            .ok_or(MaaError::TwoFactorCodeNotMatch)?;
+
+        self.user_repository
+            .enable_two_factor(user_id, totp_step(step)?)
+            .await
+    }
+
+    /// 关闭两步验证, 需要提供验证码或恢复码
+    pub async fn disable_two_factor(
+        &self,
+        user: &MaaUser,
+        req: TwoFactorCodeRequest,
+    ) -> MaaResult<()> {
+        req.validate()?;
+
+        let user_id = user.user_id.as_deref().ok_or(MaaError::NoneUserId)?;
+        if !user.totp_enabled {
+            return Err(MaaError::TwoFactorNotEnabled);
+        }
+        if !self.check_two_factor_code(user, &req.code).await? {
+            return Err(MaaError::TwoFactorCodeNotMatch);
+        }
+
+        self.user_repository
+            .update_two_factor(user_id, None, &[])
+            .await
+    }
+
+    /// 校验 TOTP 验证码或恢复码, 同一个验证码以及恢复码都只能使用一次
+    async fn check_two_factor_code(
+        &self,
+        user: &MaaUser,
+        code: &str,
+    ) -> MaaResult<bool> {
+        let user_id = user.user_id.as_deref().ok_or(MaaError::NoneUserId)?;
+        let secret = user
+            .totp_secret
+            .as_deref()
+            .ok_or(MaaError::TwoFactorNotEnabled)?;
 
-        if user.is_some() {
-            return Err(MaaError::RegistrationUserExist);
+        if let Some(step) = totp_util::verify_code(secret, code)? {
+            // 同一个时间步的验证码只能使用一次, 防止验证码在有效期内被重放
+            return self
+                .user_repository
+                .accept_totp_step(user_id, totp_step(step)?)
+                .await;
         }
 
-        self.mail_service.send_vcode(&req.email).await?;
+        self.user_repository
+            .consume_recovery_code(
+                user_id,
+                &totp_util::hash_recovery_code(code),
+            )
+            .await
+    }
+
+    pub async fn get_user_profile(
+        &self,
+        user_id: &str,
+    ) -> MaaResult<MaaUserProfile> {
+        self.user_repository
+            .find_by_user_id(user_id)
+            .await?
+            .map(Into::into)
+            .ok_or(MaaError::UserNotFound)
+    }
+
+    pub async fn search_user_profiles(
+        &self,
+        query: UserSearchQuery,
+    ) -> MaaResult<Vec<MaaUserProfile>> {
+        query.validate()?;
+
+        let users = self
+            .user_repository
+            .find_by_user_name_prefix(&query.name, USER_SEARCH_LIMIT)
+            .await?;
+
+        Ok(users.into_iter().map(Into::into).collect())
+    }
+
+    pub async fn admin_search_users(
+        &self,
+        query: AdminUserQuery,
+    ) -> MaaResult<MaaAdminUserPage> {
+        query.validate()?;
+
+        let page = query.page.unwrap_or(1);
+        let size = query.size.unwrap_or(20);
+        let (users, total) = self
+            .user_repository
+            .search(query.keyword.as_deref(), page, size)
+            .await?;
+
+        Ok(MaaAdminUserPage {
+            total,
+            page,
+            size,
+            data: users.into_iter().map(Into::into).collect(),
+        })
+    }
+
+    pub async fn admin_get_user(
+        &self,
+        user_id: &str,
+    ) -> MaaResult<MaaAdminUserInfo> {
+        self.user_repository
+            .find_by_user_id(user_id)
+            .await?
+            .map(Into::into)
+            .ok_or(MaaError::UserNotFound)
+    }
+
+    /// 启用或禁用账户, 禁用时该用户的所有会话以及个人访问令牌立即失效
+    pub async fn admin_update_status(
+        &self,
+        operator: &MaaUser,
+        user_id: &str,
+        req: UpdateUserStatusRequest,
+    ) -> MaaResult<()> {
+        let target = self.find_manageable_user(operator, user_id).await?;
+
+        self.user_repository
+            .update_status(user_id, i32::from(req.enabled))
+            .await?;
+
+        if !req.enabled && target.status != 0 {
+            self.force_logout(user_id).await?;
+            // 个人访问令牌不受水位线约束, 禁用时直接删除, 重新启用后也不会恢复
+            self.user_token_repository
+                .delete_by_user_id(user_id)
+                .await?;
+        }
 
         Ok(())
     }
+
+    /// 强制用户下线
+    pub async fn admin_force_logout(
+        &self,
+        operator: &MaaUser,
+        user_id: &str,
+    ) -> MaaResult<()> {
+        self.find_manageable_user(operator, user_id).await?;
+        self.force_logout(user_id).await
+    }
+
+    pub async fn admin_assign_role(
+        &self,
+        operator: &MaaUser,
+        user_id: &str,
+        req: AssignRoleRequest,
+    ) -> MaaResult<()> {
+        self.find_manageable_user(operator, user_id).await?;
+        self.user_repository.update_role(user_id, req.role).await
+    }
+
+    /// 查找被管理的用户, 不允许管理自己, 非管理员不能管理同级或更高级的用户
+    async fn find_manageable_user(
+        &self,
+        operator: &MaaUser,
+        user_id: &str,
+    ) -> MaaResult<MaaUser> {
+        let target = self
+            .user_repository
+            .find_by_user_id(user_id)
+            .await?
+            .ok_or(MaaError::UserNotFound)?;
+
+        if operator.user_id == target.user_id
+            || (operator.role != MaaRole::Admin && target.role >= operator.role)
+        {
+            return Err(MaaError::PermissionDenied);
+        }
+
+        Ok(target)
+    }
+
+    async fn force_logout(&self, user_id: &str) -> MaaResult<()> {
+        self.user_repository.clear_sessions(user_id).await?;
+        self.revoke_all_auth_tokens(user_id).await
+    }
+
+    /// 吊销单个 auth token, 记录保留到 token 过期为止
+    pub async fn revoke_auth_token(
+        &self,
+        claims: &JwtAuthClaims,
+    ) -> MaaResult<()> {
+        let Some(jti) = &claims.jti else {
+            return Ok(());
+        };
+        let remaining = claims.exp - chrono::Utc::now().timestamp();
+        if let Ok(remaining) = u64::try_from(remaining) {
+            if remaining > 0 {
+                self.redis_cache
+                    .set_ex(&format!("RevokedAuthToken:{}", jti), 1, remaining)
+                    .await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// 吊销用户在此之前签发的所有 auth token
+    ///
+    /// 记录一个精确到毫秒的签发时间水位线, 早于该时间签发的 token 均视为无效,
+    /// 水位线在最长的 auth token 有效期过后即可丢弃
+    pub async fn revoke_all_auth_tokens(&self, user_id: &str) -> MaaResult<()> {
+        self.redis_cache
+            .set_ex(
+                &format!("AuthTokenNotBefore:{}", user_id),
+                chrono::Utc::now().timestamp_millis(),
+                self.jwt_service.auth_expire_time(),
+            )
+            .await
+    }
+
+    async fn check_auth_token_revoked(
+        &self,
+        claims: &JwtAuthClaims,
+    ) -> MaaResult<()> {
+        let jti = claims.jti.as_deref().ok_or(MaaError::JwtVerifyFailed)?;
+
+        let revoked: Option<i64> = self
+            .redis_cache
+            .get(&format!("RevokedAuthToken:{}", jti))
+            .await?;
+        if revoked.is_some() {
+            return Err(MaaError::JwtVerifyFailed);
+        }
+
+        let not_before: Option<i64> = self
+            .redis_cache
+            .get(&format!("AuthTokenNotBefore:{}", claims.sub))
+            .await?;
+        if not_before
+            .is_some_and(|not_before| issued_before(claims, not_before))
+        {
+            return Err(MaaError::JwtVerifyFailed);
+        }
+
+        Ok(())
+    }
+
+    /// 发送验证码, 发送过于频繁时不视为错误, 而是在响应中返回剩余等待时间
+    ///
+    /// 邮件在后台根据账户状态发送, 响应的内容与耗时都与邮箱是否注册无关, 避免枚举账户
+    async fn send_vcode(
+        &self,
+        email: &str,
+        kind: VCodeKind,
+    ) -> MaaResult<SendVCodeResponse> {
+        let sent = self.throttle_vcode(email, kind).await?;
+        if sent {
+            spawn_mail(send_account_mail(
+                self.user_repository.clone(),
+                Arc::clone(&self.mail_service),
+                email.to_string(),
+                kind,
+            ));
+        }
+        self.vcode_response(email, kind, sent).await
+    }
+
+    /// 记录一次验证码发送, 发送过于频繁时返回 `false`
+    async fn throttle_vcode(
+        &self,
+        email: &str,
+        kind: VCodeKind,
+    ) -> MaaResult<bool> {
+        match self.mail_service.throttle_vcode(email, kind).await {
+            Ok(()) => Ok(true),
+            Err(MaaError::VCodeSentTooFrequently) => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+
+    async fn vcode_response(
+        &self,
+        email: &str,
+        kind: VCodeKind,
+        sent: bool,
+    ) -> MaaResult<SendVCodeResponse> {
+        let retry_after =
+            self.mail_service.vcode_retry_after(email, kind).await?;
+        Ok(SendVCodeResponse { sent, retry_after })
+    }
+}
+
+/// 根据账户状态发送验证码相关的邮件
+async fn send_account_mail(
+    repository: UserRepository,
+    mail_service: Arc<MailService>,
+    email: String,
+    kind: VCodeKind,
+) -> MaaResult<()> {
+    let user = repository.find_by_email(&email).await?;
+    match (kind, user) {
+        (VCodeKind::Registration, Some(_)) => {
+            mail_service.send_already_registered(&email).await
+        }
+        (VCodeKind::Registration | VCodeKind::ChangeEmail, None)
+        | (VCodeKind::ResetPassword, Some(_)) => {
+            mail_service.deliver_vcode(&email, kind).await
+        }
+        (VCodeKind::Activation, Some(user)) if user.activation_pending => {
+            mail_service.deliver_vcode(&email, kind).await
+        }
+        _ => Ok(()),
+    }
+}
+
+/// 在后台发送邮件, 发送失败时只记录日志
+fn spawn_mail(mail: impl Future<Output = MaaResult<()>> + Send + 'static) {
+    tokio::spawn(async move {
+        if let Err(e) = mail.await {
+            tracing::warn!("Failed to send mail: {}", e);
+        }
+    });
+}
+
+fn totp_step(step: u64) -> MaaResult<i64> {
+    i64::try_from(step).map_err(|e| MaaError::TotpError(e.to_string()))
+}
+
+fn authorities(user: &MaaUser) -> Vec<String> {
+    user.role
+        .permissions()
+        .iter()
+        .map(|permission| permission.to_string())
+        .collect()
+}
+
+// 早于该值的水位线是旧版本以秒记录的
+const LEGACY_NOT_BEFORE_LIMIT: i64 = 1_000_000_000_000;
+
+/// token 是否早于吊销水位线签发
+///
+/// 以秒记录的旧水位线保持原来的语义, 与水位线同一秒签发的 token 也视为无效
+fn issued_before(claims: &JwtAuthClaims, not_before: i64) -> bool {
+    let not_before = if not_before < LEGACY_NOT_BEFORE_LIMIT {
+        (not_before + 1) * 1000
+    } else {
+        not_before
+    };
+    claims.issued_at_millis() < not_before
+}
+
+#[test]
+fn t_token_issued_in_revocation_second() {
+    let claims = |iat_ms: i64| JwtAuthClaims {
+        sub: "id".to_string(),
+        jti: Some("jti".to_string()),
+        role: String::new(),
+        auth: Vec::new(),
+        iat: iat_ms / 1000,
+        iat_ms: Some(iat_ms),
+        exp: 0,
+        nbf: 0,
+        typ: "auth".to_string(),
+    };
+    let revoked_at = 1_700_000_000_300;
+    // 吊销前同一秒签发的 token 无效, 吊销后同一秒重新登录得到的 token 有效
+    assert!(issued_before(&claims(1_700_000_000_100), revoked_at));
+    assert!(!issued_before(&claims(1_700_000_000_500), revoked_at));
+    assert!(issued_before(&claims(1_700_000_000_500), 1_700_000_000));
 }
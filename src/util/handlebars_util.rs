@@ -2,7 +2,41 @@ use serde_json::json;
 
 use crate::MaaResult;
 
-pub fn render_vcode_email(vcode: &str) -> MaaResult<String> {
+/// 渲染验证码邮件, `content` 为 `templates` 下的验证码模板名称,
+/// 例: `vcode`、`reset-password`
+pub fn render_vcode_email(content: &str, vcode: &str) -> MaaResult<String> {
+    let data = json!({
+        "content": content,
+        "vcode": vcode,
+    });
+
+    render_email(&data)
+}
+
+/// 渲染登录失败次数过多的提醒邮件
+pub fn render_login_alert_email(
+    ip: &str,
+    lock_minutes: u64,
+) -> MaaResult<String> {
+    let data = json!({
+        "content": "login-alert",
+        "ip": ip,
+        "lock_minutes": lock_minutes,
+    });
+
+    render_email(&data)
+}
+
+/// 渲染邮箱已注册的提醒邮件, 替代向已注册邮箱发送的注册验证码
+pub fn render_already_registered_email() -> MaaResult<String> {
+    let data = json!({
+        "content": "already-registered",
+    });
+
+    render_email(&data)
+}
+
+fn render_email(data: &serde_json::Value) -> MaaResult<String> {
     let mut reg = handlebars::Handlebars::new();
 
     reg.register_template_file("root", "templates/mail-includeHtml.hbs")?;
@@ -11,12 +45,24 @@ pub fn render_vcode_email(vcode: &str) -> MaaResult<String> {
 
     reg.register_template_file("vcode", "templates/mail-vcode.hbs")?;
 
-    let data = json!({
-        "content": "vcode",
-        "vcode": vcode,
-    });
+    reg.register_template_file(
+        "reset-password",
+        "templates/mail-reset-password.hbs",
+    )?;
+
+    reg.register_template_file("activation", "templates/mail-activation.hbs")?;
+
+    reg.register_template_file(
+        "login-alert",
+        "templates/mail-login-alert.hbs",
+    )?;
 
-    let rendered = reg.render("root", &data)?;
+    reg.register_template_file(
+        "already-registered",
+        "templates/mail-already-registered.hbs",
+    )?;
+
+    let rendered = reg.render("root", data)?;
 
     Ok(rendered)
 }
@@ -24,6 +70,35 @@ pub fn render_vcode_email(vcode: &str) -> MaaResult<String> {
 #[test]
 fn t_render_vcode_email() {
     let vcode = "123456";
-    let result = render_vcode_email(vcode).unwrap();
+    let result = render_vcode_email("vcode", vcode).unwrap();
     println!("{}", result);
 }
+
+#[test]
+fn t_render_reset_password_email() {
+    let vcode = "123456";
+    let result = render_vcode_email("reset-password", vcode).unwrap();
+    assert!(result.contains(vcode));
+    assert!(result.contains("重置你的密码"));
+}
+
+#[test]
+fn t_render_login_alert_email() {
+    let result = render_login_alert_email("10.0.0.1", 15).unwrap();
+    assert!(result.contains("10.0.0.1"));
+    assert!(result.contains("15 分钟"));
+}
+
+#[test]
+fn t_render_activation_email() {
+    let vcode = "123456";
+    let result = render_vcode_email("activation", vcode).unwrap();
+    assert!(result.contains(vcode));
+    assert!(result.contains("激活你的账户"));
+}
+
+#[test]
+fn t_render_already_registered_email() {
+    let result = render_already_registered_email().unwrap();
+    assert!(result.contains("该邮箱已注册"));
+}
@@ -0,0 +1,37 @@
+use git2::{ObjectType, Oid};
+use sha2::{Digest, Sha256};
+
+use crate::MaaResult;
+
+/// 计算 sha256 并以小写十六进制表示
+pub fn sha256_hex(input: &[u8]) -> String {
+    Sha256::digest(input)
+        .iter()
+        .fold(String::new(), |mut hex, b| {
+            hex.push(char::from_digit(u32::from(b >> 4), 16).unwrap_or('0'));
+            hex.push(char::from_digit(u32::from(b & 0xf), 16).unwrap_or('0'));
+            hex
+        })
+}
+
+/// 计算文件内容的 git blob sha, 与 GitHub tree 中的 sha 一致
+pub fn git_blob_sha(content: &[u8]) -> MaaResult<String> {
+    Ok(Oid::hash_object(ObjectType::Blob, content)?.to_string())
+}
+
+#[test]
+fn t_sha256_hex() {
+    assert_eq!(
+        sha256_hex(b"abc"),
+        "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+    );
+}
+
+#[test]
+fn t_git_blob_sha() {
+    // 与 `echo hello | git hash-object --stdin` 的结果一致
+    assert_eq!(
+        git_blob_sha(b"hello\n").unwrap(),
+        "ce013625030ba8dba906f756967f9e9ca394464a"
+    );
+}
@@ -0,0 +1,117 @@
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use jsonwebtokens::AlgorithmID;
+use serde_json::{json, Value};
+
+use crate::{MaaError, MaaResult};
+
+const TAG_INTEGER: u8 = 0x02;
+const TAG_BIT_STRING: u8 = 0x03;
+const TAG_SEQUENCE: u8 = 0x30;
+
+/// 将 PEM 格式的公钥转换为 JWK, 用于发布到 `/.well-known/jwks.json`
+///
+/// 支持 SubjectPublicKeyInfo(`PUBLIC KEY`) 格式的 RSA/P-256 公钥,
+/// 以及 PKCS#1(`RSA PUBLIC KEY`) 格式的 RSA 公钥
+pub fn public_key_to_jwk(
+    alg: AlgorithmID,
+    kid: &str,
+    public_key_pem: &[u8],
+) -> MaaResult<Value> {
+    let pem = pem::parse(public_key_pem)
+        .map_err(|e| MaaError::JwtKeyError(e.to_string()))?;
+    let der = pem.contents();
+
+    match alg {
+        AlgorithmID::RS256 => {
+            let pkcs1 = if pem.tag() == "RSA PUBLIC KEY" {
+                der
+            } else {
+                spki_public_key(der)?
+            };
+            let (_, rsa_key, _) = read_tlv(pkcs1, TAG_SEQUENCE)?;
+            let (_, n, rest) = read_tlv(rsa_key, TAG_INTEGER)?;
+            let (_, e, _) = read_tlv(rest, TAG_INTEGER)?;
+            Ok(json!({
+                "kty": "RSA",
+                "use": "sig",
+                "alg": "RS256",
+                "kid": kid,
+                "n": URL_SAFE_NO_PAD.encode(strip_leading_zeros(n)),
+                "e": URL_SAFE_NO_PAD.encode(strip_leading_zeros(e)),
+            }))
+        }
+        AlgorithmID::ES256 => {
+            // 未压缩的椭圆曲线点: 0x04 || x || y
+            let point = spki_public_key(der)?;
+            let (x, y) = match point {
+                [0x04, coords @ ..] if coords.len() == 64 => {
+                    coords.split_at(32)
+                }
+                _ => {
+                    return Err(MaaError::JwtKeyError(
+                        "unsupported EC public key, expected P-256".to_string(),
+                    ))
+                }
+            };
+            Ok(json!({
+                "kty": "EC",
+                "use": "sig",
+                "alg": "ES256",
+                "kid": kid,
+                "crv": "P-256",
+                "x": URL_SAFE_NO_PAD.encode(x),
+                "y": URL_SAFE_NO_PAD.encode(y),
+            }))
+        }
+        _ => Err(MaaError::JwtKeyError(format!(
+            "{:?} has no public key to publish",
+            alg
+        ))),
+    }
+}
+
+/// 从 SubjectPublicKeyInfo 中取出 subjectPublicKey 的内容
+fn spki_public_key(der: &[u8]) -> MaaResult<&[u8]> {
+    let (_, spki, _) = read_tlv(der, TAG_SEQUENCE)?;
+    let (_, _algorithm, rest) = read_tlv(spki, TAG_SEQUENCE)?;
+    let (_, bits, _) = read_tlv(rest, TAG_BIT_STRING)?;
+    // BIT STRING 的第一个字节为未使用的位数, 公钥总是按字节对齐
+    match bits {
+        [0, key @ ..] => Ok(key),
+        _ => Err(MaaError::JwtKeyError("malformed public key".to_string())),
+    }
+}
+
+/// 读取一个 DER 编码的 TLV, 返回 (tag, value, 剩余部分)
+fn read_tlv(input: &[u8], expected_tag: u8) -> MaaResult<(u8, &[u8], &[u8])> {
+    let malformed = || MaaError::JwtKeyError("malformed DER".to_string());
+
+    let (&tag, rest) = input.split_first().ok_or_else(malformed)?;
+    if tag != expected_tag {
+        return Err(malformed());
+    }
+    let (&first, rest) = rest.split_first().ok_or_else(malformed)?;
+    let (len, rest) = if first & 0x80 == 0 {
+        (usize::from(first), rest)
+    } else {
+        let len_bytes = usize::from(first & 0x7f);
+        if len_bytes == 0 || len_bytes > 4 || rest.len() < len_bytes {
+            return Err(malformed());
+        }
+        let (len, rest) = rest.split_at(len_bytes);
+        let len = len
+            .iter()
+            .fold(0usize, |acc, &b| (acc << 8) | usize::from(b));
+        (len, rest)
+    };
+    if rest.len() < len {
+        return Err(malformed());
+    }
+    let (value, rest) = rest.split_at(len);
+    Ok((tag, value, rest))
+}
+
+fn strip_leading_zeros(bytes: &[u8]) -> &[u8] {
+    let start = bytes.iter().position(|&b| b != 0).unwrap_or(bytes.len());
+    bytes.get(start..).unwrap_or_default()
+}
@@ -1,4 +1,9 @@
 pub mod handlebars_util;
+pub mod hash_util;
+pub mod jwk;
 pub mod password_encoder;
+pub mod permission;
 pub mod redis_cache;
+pub mod regex_util;
 pub mod request_ext;
+pub mod totp_util;
@@ -1,57 +1,275 @@
-use tokio::time::Instant;
-use tracing::Level;
+use std::{str::FromStr, sync::Arc};
 
-use crate::MaaResult;
+use argon2::{
+    password_hash::{
+        rand_core::OsRng, PasswordHash, PasswordHasher as _,
+        PasswordVerifier as _, SaltString,
+    },
+    Algorithm, Argon2, Params, Version,
+};
+use bcrypt::HashParts;
+use tokio::sync::Semaphore;
 
-pub struct PasswordEncoder {
-    cost: u32,
+use crate::{
+    envs::{
+        argon2_memory_cost, argon2_parallelism, argon2_time_cost, bcrypt_cost,
+        password_hash_algorithm, password_hash_concurrency,
+        password_hash_queue_size,
+    },
+    MaaError, MaaResult,
+};
+
+const DEFAULT_BCRYPT_COST: u32 = 10;
+// 最多排队等待的哈希任务数, 超出时直接拒绝请求
+const DEFAULT_QUEUE_SIZE: usize = 64;
+
+/// 一种密码哈希算法
+pub trait PasswordHasher: Send + Sync {
+    fn encode(&self, password: &str) -> MaaResult<String>;
+
+    fn matches(&self, password: &str, hash: &str) -> MaaResult<bool>;
+
+    /// 哈希是否由该算法生成
+    fn supports(&self, hash: &str) -> bool;
+
+    /// 哈希的参数是否弱于当前配置, 仅在 `supports` 为 true 时调用
+    ///
+    /// 参数更强的旧哈希保持不变, 避免降低已有密码的强度
+    fn needs_rehash(&self, hash: &str) -> bool;
 }
 
-impl Default for PasswordEncoder {
-    fn default() -> Self {
-        Self::new()
-    }
+pub struct BcryptHasher {
+    cost: u32,
 }
 
-impl PasswordEncoder {
-    pub fn new() -> Self {
-        let span = tracing::span!(Level::INFO, "Initializing PasswordEncoder");
-        let _ = span.enter();
-        let mut cost = 4u32;
-        let test_password = "password";
-        // find a cost that makes it about 1000ms to hash
-        loop {
-            let now = Instant::now();
-            #[allow(clippy::expect_used)]
-            bcrypt::hash(test_password, cost).expect("Failed to hash password");
-            let elapsed = now.elapsed();
-            if elapsed.as_millis() > 1000 {
-                tracing::info!(
-                    "Found cost: {}, hash time is {} ms",
-                    cost,
-                    elapsed.as_millis()
-                );
-                break;
-            }
-            if cost >= 31 {
-                tracing::warn!(
-                    "Cost is too high, hash time is {} ms",
-                    elapsed.as_millis()
-                );
-                break;
-            }
-            cost += 1;
-        }
+impl BcryptHasher {
+    pub fn new(cost: u32) -> Self {
         Self { cost }
     }
+}
 
-    pub fn encode(&self, password: &str) -> MaaResult<String> {
+impl PasswordHasher for BcryptHasher {
+    fn encode(&self, password: &str) -> MaaResult<String> {
         let encoded = bcrypt::hash(password, self.cost)?;
         Ok(encoded)
     }
 
-    pub fn matches(&self, password: &str, hash: &str) -> MaaResult<bool> {
+    fn matches(&self, password: &str, hash: &str) -> MaaResult<bool> {
         let matches = bcrypt::verify(password, hash)?;
         Ok(matches)
     }
+
+    fn supports(&self, hash: &str) -> bool {
+        hash.starts_with("$2")
+    }
+
+    fn needs_rehash(&self, hash: &str) -> bool {
+        HashParts::from_str(hash)
+            .map_or(true, |parts| parts.get_cost() < self.cost)
+    }
+}
+
+pub struct Argon2Hasher {
+    argon2: Argon2<'static>,
+}
+
+impl Argon2Hasher {
+    pub fn new(
+        memory_cost: u32,
+        time_cost: u32,
+        parallelism: u32,
+    ) -> MaaResult<Self> {
+        let params = Params::new(memory_cost, time_cost, parallelism, None)
+            .map_err(|e| MaaError::PasswordHasherError(e.to_string()))?;
+        Ok(Self {
+            argon2: Argon2::new(Algorithm::Argon2id, Version::V0x13, params),
+        })
+    }
+}
+
+impl PasswordHasher for Argon2Hasher {
+    fn encode(&self, password: &str) -> MaaResult<String> {
+        let salt = SaltString::generate(&mut OsRng);
+        let encoded = self
+            .argon2
+            .hash_password(password.as_bytes(), &salt)?
+            .to_string();
+        Ok(encoded)
+    }
+
+    fn matches(&self, password: &str, hash: &str) -> MaaResult<bool> {
+        let hash = PasswordHash::new(hash)?;
+        match self.argon2.verify_password(password.as_bytes(), &hash) {
+            Ok(()) => Ok(true),
+            Err(argon2::password_hash::Error::Password) => Ok(false),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn supports(&self, hash: &str) -> bool {
+        PasswordHash::new(hash)
+            .is_ok_and(|hash| hash.algorithm == Algorithm::Argon2id.ident())
+    }
+
+    fn needs_rehash(&self, hash: &str) -> bool {
+        let Ok(hash) = PasswordHash::new(hash) else {
+            return true;
+        };
+        let Ok(params) = Params::try_from(&hash) else {
+            return true;
+        };
+        let current = self.argon2.params();
+        hash.version != Some(Version::V0x13.into())
+            || params.m_cost() < current.m_cost()
+            || params.t_cost() < current.t_cost()
+    }
+}
+
+/// 使用配置的算法生成新的哈希, 同时能够验证其他算法生成的旧哈希
+///
+/// 哈希在 tokio 的阻塞线程池中执行, 同时执行的数量不超过 `concurrency`,
+/// 排队的任务超过 `queue_size` 时返回 `MaaError::ServiceBusy`
+pub struct PasswordEncoder {
+    target: Arc<dyn PasswordHasher>,
+    fallbacks: Vec<Arc<dyn PasswordHasher>>,
+    // 正在执行以及排队中的任务
+    admission: Arc<Semaphore>,
+    // 正在执行的任务
+    workers: Arc<Semaphore>,
+}
+
+impl PasswordEncoder {
+    pub fn new(
+        target: Arc<dyn PasswordHasher>,
+        concurrency: usize,
+        queue_size: usize,
+    ) -> Self {
+        let concurrency = concurrency.max(1);
+        Self {
+            target,
+            fallbacks: vec![
+                Arc::new(BcryptHasher::new(DEFAULT_BCRYPT_COST)),
+                // 参数只影响新生成的哈希, 验证时使用哈希自带的参数
+                Arc::new(Argon2Hasher {
+                    argon2: Argon2::default(),
+                }),
+            ],
+            admission: Arc::new(Semaphore::new(concurrency + queue_size)),
+            workers: Arc::new(Semaphore::new(concurrency)),
+        }
+    }
+
+    pub fn from_env() -> MaaResult<Self> {
+        let algorithm =
+            password_hash_algorithm().unwrap_or_else(|_| "bcrypt".to_string());
+        let target: Arc<dyn PasswordHasher> = match algorithm.as_str() {
+            "bcrypt" => Arc::new(BcryptHasher::new(
+                bcrypt_cost().unwrap_or(DEFAULT_BCRYPT_COST),
+            )),
+            "argon2id" => Arc::new(Argon2Hasher::new(
+                argon2_memory_cost().unwrap_or(Params::DEFAULT_M_COST),
+                argon2_time_cost().unwrap_or(Params::DEFAULT_T_COST),
+                argon2_parallelism().unwrap_or(Params::DEFAULT_P_COST),
+            )?),
+            other => {
+                return Err(MaaError::PasswordHasherError(format!(
+                    "unsupported algorithm: {}",
+                    other
+                )))
+            }
+        };
+        let concurrency = password_hash_concurrency().unwrap_or_else(|_| {
+            std::thread::available_parallelism().map_or(1, Into::into)
+        });
+        let queue_size =
+            password_hash_queue_size().unwrap_or(DEFAULT_QUEUE_SIZE);
+        Ok(Self::new(target, concurrency, queue_size))
+    }
+
+    pub async fn encode(&self, password: &str) -> MaaResult<String> {
+        let hasher = Arc::clone(&self.target);
+        let password = password.to_string();
+        self.run_blocking(move || hasher.encode(&password)).await
+    }
+
+    pub async fn matches(&self, password: &str, hash: &str) -> MaaResult<bool> {
+        let Some(hasher) = self.hasher_for(hash) else {
+            return Ok(false);
+        };
+        let password = password.to_string();
+        let hash = hash.to_string();
+        self.run_blocking(move || hasher.matches(&password, &hash))
+            .await
+    }
+
+    /// 哈希不是由当前配置的算法和参数生成时, 需要在登录成功后重新哈希
+    pub fn needs_rehash(&self, hash: &str) -> bool {
+        !self.target.supports(hash) || self.target.needs_rehash(hash)
+    }
+
+    fn hasher_for(&self, hash: &str) -> Option<Arc<dyn PasswordHasher>> {
+        std::iter::once(&self.target)
+            .chain(self.fallbacks.iter())
+            .find(|hasher| hasher.supports(hash))
+            .map(Arc::clone)
+    }
+
+    async fn run_blocking<T, F>(&self, f: F) -> MaaResult<T>
+    where
+        T: Send + 'static,
+        F: FnOnce() -> MaaResult<T> + Send + 'static,
+    {
+        let admitted = Arc::clone(&self.admission)
+            .try_acquire_owned()
+            .map_err(|_| MaaError::ServiceBusy)?;
+        let worker = Arc::clone(&self.workers)
+            .acquire_owned()
+            .await
+            .map_err(|_| MaaError::ServiceBusy)?;
+        // permit 随任务一起移动, 请求被取消时任务仍然占用名额直到执行完毕
+        let permits = (admitted, worker);
+        tokio::task::spawn_blocking(move || {
+            let _permits = permits;
+            f()
+        })
+        .await?
+    }
+}
+
+#[tokio::test]
+async fn t_rehash_on_algorithm_or_cost_change() {
+    let encoder = |hasher: Arc<dyn PasswordHasher>| {
+        PasswordEncoder::new(hasher, 1, DEFAULT_QUEUE_SIZE)
+    };
+
+    let bcrypt = encoder(Arc::new(BcryptHasher::new(4)));
+    let hash = bcrypt.encode("password").await.unwrap();
+    assert!(bcrypt.matches("password", &hash).await.unwrap());
+    assert!(!bcrypt.needs_rehash(&hash));
+
+    let stronger = encoder(Arc::new(BcryptHasher::new(5)));
+    assert!(stronger.matches("password", &hash).await.unwrap());
+    assert!(stronger.needs_rehash(&hash));
+
+    let argon2 = encoder(Arc::new(Argon2Hasher::new(1024, 1, 1).unwrap()));
+    assert!(argon2.matches("password", &hash).await.unwrap());
+    assert!(argon2.needs_rehash(&hash));
+
+    let upgraded = argon2.encode("password").await.unwrap();
+    assert!(upgraded.starts_with("$argon2id$"));
+    assert!(argon2.matches("password", &upgraded).await.unwrap());
+    assert!(!argon2.matches("wrong", &upgraded).await.unwrap());
+    assert!(!argon2.needs_rehash(&upgraded));
+    assert!(bcrypt.matches("password", &upgraded).await.unwrap());
+    assert!(bcrypt.needs_rehash(&upgraded));
+}
+
+#[test]
+fn t_no_rehash_to_lower_cost() {
+    // cost 为 12 的哈希, 直接使用常量避免在测试中计算
+    let hash = "$2b$12$R9h/cIPz0gi.URNNX3kh2OPST9/PgBkqquzi.Ss7KIUgO2t0jWMUW";
+    let hasher = BcryptHasher::new(DEFAULT_BCRYPT_COST);
+    assert!(hasher.supports(hash));
+    assert!(!hasher.needs_rehash(hash));
+    assert!(BcryptHasher::new(13).needs_rehash(hash));
 }
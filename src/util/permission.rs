@@ -0,0 +1,120 @@
+use serde::{Deserialize, Serialize};
+
+/*
+ * 权限名称, 格式为 `资源:操作`
+ */
+
+pub const COMMENT_CREATE: &str = "comment:create";
+pub const COPILOT_RATE: &str = "copilot:rate";
+pub const COPILOT_UPLOAD: &str = "copilot:upload";
+// 上传的作业无需审核直接公开
+pub const COPILOT_SKIP_REVIEW: &str = "copilot:skip_review";
+pub const COPILOT_DELETE_ANY: &str = "copilot:delete_any";
+pub const COMMENT_DELETE_ANY: &str = "comment:delete_any";
+// 查看、搜索、启用/禁用用户以及强制下线
+pub const USER_MANAGE: &str = "user:manage";
+pub const USER_ASSIGN_ROLE: &str = "user:assign_role";
+
+const USER_PERMISSIONS: &[&str] =
+    &[COMMENT_CREATE, COPILOT_RATE, COPILOT_UPLOAD];
+
+const UPLOADER_PERMISSIONS: &[&str] = &[
+    COMMENT_CREATE,
+    COPILOT_RATE,
+    COPILOT_UPLOAD,
+    COPILOT_SKIP_REVIEW,
+];
+
+const MODERATOR_PERMISSIONS: &[&str] = &[
+    COMMENT_CREATE,
+    COPILOT_RATE,
+    COPILOT_UPLOAD,
+    COPILOT_SKIP_REVIEW,
+    COPILOT_DELETE_ANY,
+    COMMENT_DELETE_ANY,
+    USER_MANAGE,
+];
+
+const ADMIN_PERMISSIONS: &[&str] = &[
+    COMMENT_CREATE,
+    COPILOT_RATE,
+    COPILOT_UPLOAD,
+    COPILOT_SKIP_REVIEW,
+    COPILOT_DELETE_ANY,
+    COMMENT_DELETE_ANY,
+    USER_MANAGE,
+    USER_ASSIGN_ROLE,
+];
+
+/// 用户角色, 每个角色拥有一组固定的权限, 高级角色包含低级角色的所有权限
+// 变体按照权限从低到高排列, 用于比较角色的高低
+#[derive(
+    Serialize,
+    Deserialize,
+    Debug,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Default,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum MaaRole {
+    #[default]
+    User,
+    Uploader,
+    Moderator,
+    Admin,
+}
+
+impl MaaRole {
+    pub const ALL: [MaaRole; 4] = [
+        MaaRole::User,
+        MaaRole::Uploader,
+        MaaRole::Moderator,
+        MaaRole::Admin,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            MaaRole::User => "user",
+            MaaRole::Uploader => "uploader",
+            MaaRole::Moderator => "moderator",
+            MaaRole::Admin => "admin",
+        }
+    }
+
+    pub fn permissions(self) -> &'static [&'static str] {
+        match self {
+            MaaRole::User => USER_PERMISSIONS,
+            MaaRole::Uploader => UPLOADER_PERMISSIONS,
+            MaaRole::Moderator => MODERATOR_PERMISSIONS,
+            MaaRole::Admin => ADMIN_PERMISSIONS,
+        }
+    }
+
+    pub fn has_permission(self, permission: &str) -> bool {
+        self.permissions().contains(&permission)
+    }
+}
+
+#[test]
+fn t_role_permissions_are_cumulative() {
+    let roles = [
+        MaaRole::User,
+        MaaRole::Uploader,
+        MaaRole::Moderator,
+        MaaRole::Admin,
+    ];
+    for pair in roles.windows(2) {
+        if let [lower, higher] = pair {
+            for permission in lower.permissions() {
+                assert!(higher.has_permission(permission));
+            }
+        }
+    }
+    assert!(!MaaRole::Moderator.has_permission(USER_ASSIGN_ROLE));
+    assert!(MaaRole::Admin.has_permission(USER_ASSIGN_ROLE));
+}
@@ -71,6 +71,34 @@ impl RedisCache {
         Ok(result)
     }
 
+    /// 计数器加一, 返回加一后的值
+    pub async fn incr(&self, key: &str) -> MaaResult<u64> {
+        let mut conn = self.pool.get().await?;
+        let count: u64 = conn.incr(key, 1).await?;
+        Ok(count)
+    }
+
+    /// 计数器加一并刷新过期时间, 返回加一后的值
+    pub async fn incr_ex(&self, key: &str, seconds: u64) -> MaaResult<u64> {
+        let mut conn = self.pool.get().await?;
+        let count: u64 = conn.incr(key, 1).await?;
+        let _: () = conn.expire(key, seconds as i64).await?;
+        Ok(count)
+    }
+
+    pub async fn delete(&self, key: &str) -> MaaResult<()> {
+        let mut conn = self.pool.get().await?;
+        let _: () = conn.del(key).await?;
+        Ok(())
+    }
+
+    /// 获取 key 的剩余过期秒数, key 不存在或未设置过期时间时返回 `None`
+    pub async fn ttl(&self, key: &str) -> MaaResult<Option<u64>> {
+        let mut conn = self.pool.get().await?;
+        let ttl: i64 = conn.ttl(key).await?;
+        Ok(u64::try_from(ttl).ok())
+    }
+
     pub async fn delete_if_equals<
         T: ToRedisArgs + FromRedisValue + Send + Sync + PartialEq,
     >(
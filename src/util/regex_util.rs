@@ -0,0 +1,21 @@
+// 正则表达式中需要转义的字符
+const REGEX_META_CHARS: &str = r"\.+*?()|[]{}^$#-&~";
+
+/// 转义正则表达式中的特殊字符, 用于将用户输入安全地拼接进 MongoDB 的 `$regex`
+pub fn escape_regex(input: &str) -> String {
+    let mut escaped = String::with_capacity(input.len());
+    for c in input.chars() {
+        if REGEX_META_CHARS.contains(c) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+#[test]
+fn t_escape_regex() {
+    assert_eq!(escape_regex("1-7"), r"1\-7");
+    assert_eq!(escape_regex("(a+)+$"), r"\(a\+\)\+\$");
+    assert_eq!(escape_regex("怒号光明"), "怒号光明");
+}
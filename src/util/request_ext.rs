@@ -1,8 +1,14 @@
-use std::net::SocketAddr;
+use std::{
+    net::{IpAddr, SocketAddr},
+    sync::OnceLock,
+};
 
 use axum::extract::Request;
+use http::{request::Parts, HeaderMap};
 use local_ip_address::local_ip;
 
+use crate::envs::trusted_proxies;
+
 pub trait RequestExt {
     fn get_ip_addr(&self, sokect_addr: Option<SocketAddr>) -> String;
 }
@@ -10,9 +16,8 @@ pub trait RequestExt {
 const UNKNOWN_IP: &str = "unknown";
 
 macro_rules! header_or_empty {
-    ($request:expr, $header:expr) => {
-        $request
-            .headers()
+    ($headers:expr, $header:expr) => {
+        $headers
             .get($header)
             .and_then(|header| header.to_str().ok())
             .unwrap_or("")
@@ -21,38 +26,151 @@ macro_rules! header_or_empty {
 
 impl RequestExt for Request {
     fn get_ip_addr(&self, socket_addr: Option<SocketAddr>) -> String {
-        let mut ip = header_or_empty!(self, "x-forwarded-for");
+        ip_addr_from_headers(self.headers(), socket_addr, proxies())
+    }
+}
 
-        if ip.is_empty() || ip.eq_ignore_ascii_case(UNKNOWN_IP) {
-            ip = header_or_empty!(self, "Proxy-Client-IP");
-        }
+impl RequestExt for Parts {
+    fn get_ip_addr(&self, socket_addr: Option<SocketAddr>) -> String {
+        ip_addr_from_headers(&self.headers, socket_addr, proxies())
+    }
+}
 
-        if ip.is_empty() || ip.eq_ignore_ascii_case(UNKNOWN_IP) {
-            ip = header_or_empty!(self, "WL-Proxy-Client-IP");
-        }
+/// 一个可信代理的地址或网段, 例: `10.0.0.1`、`10.0.0.0/8`
+#[derive(Debug, Clone, Copy)]
+struct ProxyNet {
+    addr: IpAddr,
+    prefix: u32,
+}
 
-        let mut ip = if ip.is_empty() || ip.eq_ignore_ascii_case(UNKNOWN_IP) {
-            let mut ip_str = socket_addr
-                .as_ref()
-                .map(|addr| addr.ip().to_string())
-                .unwrap_or_default();
-            if ip_str.eq("127.0.0.1") {
-                if let Ok(local_ip) = local_ip() {
-                    ip_str = local_ip.to_string();
-                }
-            }
-            ip_str
-        } else {
-            ip.into()
+impl ProxyNet {
+    fn parse(s: &str) -> Option<Self> {
+        let (addr, prefix) = match s.split_once('/') {
+            Some((addr, prefix)) => (addr, Some(prefix)),
+            None => (s, None),
         };
+        let addr: IpAddr = addr.trim().parse().ok()?;
+        let max = if addr.is_ipv4() { 32 } else { 128 };
+        let prefix = match prefix {
+            Some(prefix) => prefix.trim().parse().ok().filter(|p| *p <= max)?,
+            None => max,
+        };
+        Some(Self { addr, prefix })
+    }
 
-        // 对于通过多个代理的情况，第一个IP为客户端真实IP,多个IP按照','分割
-        if ip.len() > 15 {
-            if let Some(index) = ip.find(',') {
-                ip = ip.split_at(index).0.to_string();
+    fn contains(&self, ip: IpAddr) -> bool {
+        let (net, ip, bits) = match (self.addr, ip.to_canonical()) {
+            (IpAddr::V4(net), IpAddr::V4(ip)) => {
+                (u32::from(net) as u128, u32::from(ip) as u128, 32)
             }
+            (IpAddr::V6(net), IpAddr::V6(ip)) => {
+                (u128::from(net), u128::from(ip), 128)
+            }
+            _ => return false,
+        };
+        let shift = bits - self.prefix;
+        shift >= bits || net >> shift == ip >> shift
+    }
+}
+
+/// 从 `TRUSTED_PROXIES` 读取的可信代理, 未配置时不信任任何代理
+fn proxies() -> &'static [ProxyNet] {
+    static PROXIES: OnceLock<Vec<ProxyNet>> = OnceLock::new();
+    PROXIES.get_or_init(|| {
+        let Ok(config) = trusted_proxies() else {
+            return Vec::new();
+        };
+        config
+            .split(',')
+            .map(str::trim)
+            .filter(|s| !s.is_empty())
+            .filter_map(|s| {
+                let net = ProxyNet::parse(s);
+                if net.is_none() {
+                    tracing::warn!("Invalid trusted proxy: {}", s);
+                }
+                net
+            })
+            .collect()
+    })
+}
+
+fn is_trusted(proxies: &[ProxyNet], ip: IpAddr) -> bool {
+    proxies.iter().any(|net| net.contains(ip))
+}
+
+/// 获取客户端 IP, 默认使用连接的对端地址
+///
+/// 只有对端是可信代理时才读取代理请求头, `X-Forwarded-For` 从右往左跳过可信代理,
+/// 第一个不可信的地址即为客户端, 客户端自行添加的地址位于左侧不会被使用
+fn ip_addr_from_headers(
+    headers: &HeaderMap,
+    socket_addr: Option<SocketAddr>,
+    proxies: &[ProxyNet],
+) -> String {
+    let peer = socket_addr.map(|addr| addr.ip());
+    if let Some(peer) = peer.filter(|peer| is_trusted(proxies, *peer)) {
+        if let Some(ip) = forwarded_ip(headers, proxies) {
+            return ip;
         }
+        tracing::debug!("No client ip forwarded by proxy {}", peer);
+    }
 
-        ip
+    let mut ip_str = peer.map(|ip| ip.to_string()).unwrap_or_default();
+    if ip_str.eq("127.0.0.1") {
+        if let Ok(local_ip) = local_ip() {
+            ip_str = local_ip.to_string();
+        }
     }
+    ip_str
+}
+
+fn forwarded_ip(headers: &HeaderMap, proxies: &[ProxyNet]) -> Option<String> {
+    let forwarded: Vec<IpAddr> = headers
+        .get_all("x-forwarded-for")
+        .iter()
+        .filter_map(|header| header.to_str().ok())
+        .flat_map(|header| header.split(','))
+        .filter_map(|ip| ip.trim().parse().ok())
+        .collect();
+    // 全部是可信代理时, 最左侧的地址就是发起请求的客户端
+    if let Some(ip) = forwarded
+        .iter()
+        .rev()
+        .find(|ip| !is_trusted(proxies, **ip))
+        .or(forwarded.first())
+    {
+        return Some(ip.to_string());
+    }
+
+    ["Proxy-Client-IP", "WL-Proxy-Client-IP"]
+        .into_iter()
+        .map(|header| header_or_empty!(headers, header).trim())
+        .find(|ip| !ip.is_empty() && !ip.eq_ignore_ascii_case(UNKNOWN_IP))
+        .map(str::to_string)
+}
+
+#[test]
+fn t_forwarded_ip_only_from_trusted_proxy() {
+    let proxies: Vec<ProxyNet> = ["10.0.0.0/8", "::1"]
+        .into_iter()
+        .filter_map(ProxyNet::parse)
+        .collect();
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        "x-forwarded-for",
+        "1.1.1.1, 2.2.2.2, 10.0.0.2".parse().unwrap(),
+    );
+    let ip = |peer: &str| {
+        let addr = SocketAddr::new(peer.parse().unwrap(), 8080);
+        ip_addr_from_headers(&headers, Some(addr), &proxies)
+    };
+
+    // 不可信的对端无法伪造客户端 IP
+    assert_eq!(ip("3.3.3.3"), "3.3.3.3");
+    // 跳过可信代理后最右侧的地址, 客户端自行添加的 1.1.1.1 不会被使用
+    assert_eq!(ip("10.1.2.3"), "2.2.2.2");
+    assert_eq!(ip("::1"), "2.2.2.2");
+    assert_eq!(ip("::ffff:10.0.0.1"), "2.2.2.2");
+    assert_eq!(ip_addr_from_headers(&headers, None, &proxies), "");
 }
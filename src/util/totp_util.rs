@@ -0,0 +1,113 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use rand::{distributions::Alphanumeric, Rng, RngCore};
+use totp_rs::{Algorithm, Secret, TOTP};
+
+use crate::{util::hash_util::sha256_hex, MaaError, MaaResult};
+
+const ISSUER: &str = "MAA Copilot";
+// 兼容主流验证器应用的默认参数: SHA1、6 位、30 秒
+const DIGITS: usize = 6;
+const STEP: u64 = 30;
+// 允许前后各一个时间步长的时钟误差
+const SKEW: u8 = 1;
+const SECRET_LEN: usize = 20;
+
+const RECOVERY_CODE_COUNT: usize = 10;
+const RECOVERY_CODE_LEN: usize = 10;
+
+fn build_totp(secret: Vec<u8>, account: &str) -> MaaResult<TOTP> {
+    // 时钟误差由 `verify_code` 逐个时间步检查, 以便得到验证码对应的时间步
+    TOTP::new(
+        Algorithm::SHA1,
+        DIGITS,
+        0,
+        STEP,
+        secret,
+        Some(ISSUER.to_string()),
+        account.to_string(),
+    )
+    .map_err(|e| MaaError::TotpError(e.to_string()))
+}
+
+fn decode_secret(secret: &str) -> MaaResult<Vec<u8>> {
+    Secret::Encoded(secret.to_string())
+        .to_bytes()
+        .map_err(|e| MaaError::TotpError(e.to_string()))
+}
+
+/// 生成一个新的 base32 编码的 TOTP 密钥
+pub fn generate_secret() -> String {
+    let mut secret = vec![0u8; SECRET_LEN];
+    rand::thread_rng().fill_bytes(&mut secret);
+    Secret::Raw(secret).to_encoded().to_string()
+}
+
+/// 生成用于验证器应用扫码的 `otpauth://` URI
+pub fn otpauth_uri(secret: &str, account: &str) -> MaaResult<String> {
+    Ok(build_totp(decode_secret(secret)?, account)?.get_url())
+}
+
+/// 当前时间所在的时间步
+fn current_step() -> MaaResult<u64> {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_err(|e| MaaError::TotpError(e.to_string()))?;
+    Ok(now.as_secs() / STEP)
+}
+
+/// 校验当前时间的 TOTP 验证码, 通过时返回验证码对应的时间步,
+/// 调用方需要保证同一个时间步的验证码只被接受一次
+pub fn verify_code(secret: &str, code: &str) -> MaaResult<Option<u64>> {
+    let totp = build_totp(decode_secret(secret)?, "")?;
+    let current = current_step()?;
+    let skew = u64::from(SKEW);
+    Ok((current.saturating_sub(skew)..=current + skew)
+        .find(|step| totp.check(code.trim(), step * STEP)))
+}
+
+/// 生成一组一次性恢复码, 仅在开启两步验证时展示一次
+pub fn generate_recovery_codes() -> Vec<String> {
+    (0..RECOVERY_CODE_COUNT)
+        .map(|_| {
+            rand::thread_rng()
+                .sample_iter(&Alphanumeric)
+                .take(RECOVERY_CODE_LEN)
+                .map(|c| char::from(c).to_ascii_lowercase())
+                .collect()
+        })
+        .collect()
+}
+
+/// 恢复码只保存哈希, 比较前忽略大小写以及空白
+pub fn hash_recovery_code(code: &str) -> String {
+    let normalized: String = code
+        .chars()
+        .filter(|c| !c.is_whitespace() && *c != '-')
+        .map(|c| c.to_ascii_lowercase())
+        .collect();
+    sha256_hex(normalized.as_bytes())
+}
+
+#[test]
+fn t_totp_round_trip() {
+    let secret = generate_secret();
+    let uri = otpauth_uri(&secret, "user@example.com").unwrap();
+    assert!(uri.starts_with("otpauth://totp/"));
+    assert!(uri.contains(&format!("secret={}", secret)));
+
+    let totp = build_totp(decode_secret(&secret).unwrap(), "").unwrap();
+    let step = current_step().unwrap();
+    let code = totp.generate(step * STEP);
+    assert_eq!(verify_code(&secret, &code).unwrap(), Some(step));
+    let stale = totp.generate((step - 3) * STEP);
+    assert_eq!(verify_code(&secret, &stale).unwrap(), None);
+
+    let codes = generate_recovery_codes();
+    assert_eq!(codes.len(), RECOVERY_CODE_COUNT);
+    let first = codes.first().unwrap();
+    assert_eq!(
+        hash_recovery_code(first),
+        hash_recovery_code(&format!(" {} ", first.to_uppercase()))
+    );
+}
@@ -0,0 +1,146 @@
+use jsonwebtokens::AlgorithmID;
+use maa_backend::{
+    error::MaaError,
+    service::jwt_service::{JwtConfig, JwtService},
+};
+use serde_json::Value;
+
+fn read_key(name: &str) -> Vec<u8> {
+    std::fs::read(format!("tests/keys/{}", name)).unwrap()
+}
+
+fn es256_config(kid: &str, key: &str) -> JwtConfig {
+    JwtConfig {
+        algorithm: AlgorithmID::ES256,
+        key_id: kid.to_string(),
+        signing_key: read_key(&format!("{}-private.pem", key)),
+        public_key: Some(read_key(&format!("{}-public.pem", key))),
+        previous_keys: vec![],
+        auth_expire_time: 60,
+        refresh_expire_time: 120,
+        remember_refresh_expire_time: 240,
+    }
+}
+
+#[test]
+fn test_hs256_round_trip() {
+    let service = JwtService::from_config(JwtConfig {
+        algorithm: AlgorithmID::HS256,
+        key_id: "hs".to_string(),
+        signing_key: b"secret".to_vec(),
+        public_key: None,
+        previous_keys: vec![],
+        auth_expire_time: 60,
+        refresh_expire_time: 120,
+        remember_refresh_expire_time: 240,
+    })
+    .unwrap();
+
+    let token = service
+        .issue_auth_token(
+            "user".to_string(),
+            None,
+            "user".to_string(),
+            vec!["comment:create".to_string()],
+        )
+        .unwrap();
+    let claims = service.verify_and_parse_auth_token(&token.token).unwrap();
+    assert_eq!(claims.sub, "user");
+    assert_eq!(claims.typ, "auth");
+    assert_eq!(
+        service.jwks().pointer("/keys").and_then(Value::as_array),
+        Some(&vec![])
+    );
+}
+
+#[test]
+fn test_es256_key_rotation() {
+    let old =
+        JwtService::from_config(es256_config("old", "es256-old")).unwrap();
+    let old_token = old
+        .issue_refresh_token("user".to_string(), Some("jti".to_string()), false)
+        .unwrap();
+
+    // 未配置旧密钥时, 旧 token 无法通过验证
+    let current =
+        JwtService::from_config(es256_config("new", "es256")).unwrap();
+    assert!(matches!(
+        current.verify_and_parse_refresh_token(&old_token.token),
+        Err(MaaError::JwtVerifyFailed)
+    ));
+
+    let mut config = es256_config("new", "es256");
+    config.previous_keys =
+        vec![("old".to_string(), read_key("es256-old-public.pem"))];
+    let rotated = JwtService::from_config(config).unwrap();
+
+    let claims = rotated
+        .verify_and_parse_refresh_token(&old_token.token)
+        .unwrap();
+    assert_eq!(claims.jti.as_deref(), Some("jti"));
+
+    let new_token = rotated
+        .issue_refresh_token("user".to_string(), None, true)
+        .unwrap();
+    assert!(matches!(
+        old.verify_and_parse_refresh_token(&new_token.token),
+        Err(MaaError::JwtVerifyFailed)
+    ));
+
+    let jwks = rotated.jwks();
+    let field = |pointer: &str| jwks.pointer(pointer).and_then(Value::as_str);
+    assert_eq!(field("/keys/0/kid"), Some("new"));
+    assert_eq!(field("/keys/0/kty"), Some("EC"));
+    assert_eq!(field("/keys/0/crv"), Some("P-256"));
+    assert_eq!(field("/keys/1/kid"), Some("old"));
+    assert_eq!(jwks.pointer("/keys/2"), None);
+}
+
+#[test]
+fn test_rs256_jwks() {
+    let service = JwtService::from_config(JwtConfig {
+        algorithm: AlgorithmID::RS256,
+        key_id: "rs".to_string(),
+        signing_key: read_key("rs256-private.pem"),
+        public_key: Some(read_key("rs256-public.pem")),
+        previous_keys: vec![],
+        auth_expire_time: 60,
+        refresh_expire_time: 120,
+        remember_refresh_expire_time: 240,
+    })
+    .unwrap();
+
+    let token = service
+        .issue_auth_token("user".to_string(), None, "user".to_string(), vec![])
+        .unwrap();
+    let claims = service.verify_and_parse_auth_token(&token.token).unwrap();
+    assert_eq!(claims.sub, "user");
+
+    let jwks = service.jwks();
+    let field = |pointer: &str| jwks.pointer(pointer).and_then(Value::as_str);
+    assert_eq!(field("/keys/0/kty"), Some("RSA"));
+    assert_eq!(field("/keys/0/kid"), Some("rs"));
+    assert_eq!(field("/keys/0/e"), Some("AQAB"));
+    // 2048 位模数 base64url 编码后为 342 个字符
+    assert_eq!(field("/keys/0/n").map(str::len), Some(342));
+}
+
+#[test]
+fn test_refresh_lifetimes() {
+    let service =
+        JwtService::from_config(es256_config("new", "es256")).unwrap();
+
+    let auth = service
+        .issue_auth_token("user".to_string(), None, "user".to_string(), vec![])
+        .unwrap();
+    let refresh = service
+        .issue_refresh_token("user".to_string(), None, false)
+        .unwrap();
+    let remembered = service
+        .issue_refresh_token("user".to_string(), None, true)
+        .unwrap();
+
+    assert_eq!(auth.expires_at - auth.not_before, 60);
+    assert_eq!(refresh.expires_at - refresh.not_before, 120);
+    assert_eq!(remembered.expires_at - remembered.not_before, 240);
+}
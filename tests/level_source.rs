@@ -0,0 +1,108 @@
+use std::{
+    fs::{self, File},
+    path::{Path, PathBuf},
+};
+
+use flate2::{write::GzEncoder, Compression};
+use git2::{Repository, Signature};
+use maa_backend::service::{
+    ark_level_sync_service::parse_level,
+    level_source::{
+        open_local_source, DirectoryLevelSource, GitLevelSource, LevelSource,
+        TarballLevelSource,
+    },
+};
+
+const LEVEL_PATH: &str = "resource/Arknights-Tile-Pos";
+const LEVELS: [(&str, &str); 2] = [
+    (
+        "level_main_01-07.json",
+        r#"{"code": "1-7", "levelId": "obt/main/level_main_01-07", "name": "暴君", "stageId": "main_01-07", "width": 11, "height": 8}"#,
+    ),
+    ("overview.json", r#"{"width": 0, "height": 0}"#),
+];
+
+/// 在临时目录中创建与资源仓库相同结构的关卡目录
+fn create_resource_dir() -> PathBuf {
+    let root = std::env::temp_dir()
+        .join(format!("maa-level-source-{}", uuid::Uuid::new_v4()));
+    let level_dir = root.join(LEVEL_PATH);
+    fs::create_dir_all(&level_dir).unwrap();
+    for (name, content) in LEVELS {
+        fs::write(level_dir.join(name), content).unwrap();
+    }
+    fs::write(level_dir.join("README.md"), "not a level").unwrap();
+    root
+}
+
+fn commit_all(root: &Path) {
+    let repo = Repository::init(root).unwrap();
+    let mut index = repo.index().unwrap();
+    index
+        .add_all(["*"], git2::IndexAddOption::DEFAULT, None)
+        .unwrap();
+    let tree = repo.find_tree(index.write_tree().unwrap()).unwrap();
+    let signature = Signature::now("maa", "maa@example.com").unwrap();
+    repo.commit(Some("HEAD"), &signature, &signature, "init", &tree, &[])
+        .unwrap();
+}
+
+fn create_tarball(root: &Path) -> PathBuf {
+    let path = root.with_extension("tar.gz");
+    let encoder =
+        GzEncoder::new(File::create(&path).unwrap(), Compression::default());
+    let mut builder = tar::Builder::new(encoder);
+    builder
+        .append_dir_all("MaaResource-main/resource", root.join("resource"))
+        .unwrap();
+    builder.into_inner().unwrap().finish().unwrap();
+    path
+}
+
+/// 按文件名排序后返回 (文件名, sha, 内容)
+async fn read_all(source: &dyn LevelSource) -> Vec<(String, String, String)> {
+    let mut files = source.list_files().await.unwrap();
+    files.sort_by(|a, b| a.name.cmp(&b.name));
+    let mut result = Vec::new();
+    for file in files {
+        let content = source.read_file(&file).await.unwrap();
+        result.push((file.name, file.sha, content));
+    }
+    result
+}
+
+#[tokio::test]
+async fn t_local_sources_match_git() {
+    let root = create_resource_dir();
+    commit_all(&root);
+    let tarball = create_tarball(&root);
+
+    // git 仓库中的 blob id 即 GitHub tree 中的 sha
+    let from_git = read_all(&GitLevelSource::new(&root, "HEAD")).await;
+    let from_dir = read_all(&DirectoryLevelSource::new(&root)).await;
+    let from_level_dir =
+        read_all(&DirectoryLevelSource::new(&root.join(LEVEL_PATH))).await;
+    let from_tarball =
+        read_all(&TarballLevelSource::open(&tarball).unwrap()).await;
+
+    assert_eq!(from_git.len(), LEVELS.len());
+    assert_eq!(from_dir, from_git);
+    assert_eq!(from_level_dir, from_git);
+    assert_eq!(from_tarball, from_git);
+
+    let levels: Vec<_> = from_git
+        .into_iter()
+        .filter_map(|(_, sha, content)| parse_level(&content, sha).unwrap())
+        .collect();
+    let [level] = levels.as_slice() else {
+        panic!("expected exactly one level, got {}", levels.len());
+    };
+    assert_eq!(level.stage_id.as_deref(), Some("main_01-07"));
+
+    // 根据路径自动选择关卡源
+    let source = open_local_source(&tarball).unwrap();
+    assert_eq!(source.list_files().await.unwrap().len(), LEVELS.len());
+
+    fs::remove_dir_all(&root).unwrap();
+    fs::remove_file(&tarball).unwrap();
+}
@@ -0,0 +1,195 @@
+use std::{
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Condvar, Mutex,
+    },
+    time::Duration,
+};
+
+use axum::{body::Body, routing::get, Router};
+use http::{Request, StatusCode};
+use maa_backend::{
+    error::MaaError,
+    util::password_encoder::{PasswordEncoder, PasswordHasher},
+    MaaResult,
+};
+use tokio::task::JoinHandle;
+use tower::ServiceExt;
+
+const LOGINS: usize = 32;
+const CONCURRENCY: usize = 2;
+const QUEUE_SIZE: usize = 8;
+// 只用于防止测试失败时一直等待, 正常情况下远不会达到
+const DEADLINE: Duration = Duration::from_secs(10);
+
+/// 放行前一直阻塞的哈希算法, 哈希为 `gated$<版本>$<密码>`, 只有 v2 是最新版本
+#[derive(Default)]
+struct Gate {
+    open: Mutex<bool>,
+    opened: Condvar,
+    // 正在执行的哈希数
+    running: AtomicUsize,
+}
+
+impl Gate {
+    fn wait(&self) {
+        self.running.fetch_add(1, Ordering::SeqCst);
+        let mut open = self.open.lock().unwrap();
+        while !*open {
+            open = self.opened.wait(open).unwrap();
+        }
+    }
+
+    fn release(&self) {
+        *self.open.lock().unwrap() = true;
+        self.opened.notify_all();
+    }
+}
+
+struct GatedHasher(Arc<Gate>);
+
+impl PasswordHasher for GatedHasher {
+    fn encode(&self, password: &str) -> MaaResult<String> {
+        self.0.wait();
+        Ok(format!("gated$v2${}", password))
+    }
+
+    fn matches(&self, password: &str, hash: &str) -> MaaResult<bool> {
+        self.0.wait();
+        Ok(hash.rsplit('$').next() == Some(password))
+    }
+
+    fn supports(&self, hash: &str) -> bool {
+        hash.starts_with("gated$")
+    }
+
+    fn needs_rehash(&self, hash: &str) -> bool {
+        !hash.starts_with("gated$v2$")
+    }
+}
+
+/// 与 `UserService::login` 相同的哈希调用: 先校验密码, 旧哈希在校验通过后重新哈希
+async fn login(encoder: Arc<PasswordEncoder>, hash: String) -> MaaResult<()> {
+    if !encoder.matches("password", &hash).await? {
+        return Err(MaaError::LoginFail);
+    }
+    if encoder.needs_rehash(&hash) {
+        encoder.encode("password").await?;
+    }
+    Ok(())
+}
+
+/// 与登录无关的路由
+fn app() -> Router {
+    Router::new().route("/arknights/level", get(|| async { "[]" }))
+}
+
+async fn wait_until(mut done: impl FnMut() -> bool) {
+    tokio::time::timeout(DEADLINE, async {
+        while !done() {
+            tokio::time::sleep(Duration::from_millis(1)).await;
+        }
+    })
+    .await
+    .unwrap();
+}
+
+#[test]
+fn test_logins_do_not_starve_other_routes() {
+    // 只有两个 worker 线程, 哈希若在 worker 上执行会阻塞其他所有请求
+    let runtime = tokio::runtime::Builder::new_multi_thread()
+        .worker_threads(2)
+        .enable_time()
+        .build()
+        .unwrap();
+
+    runtime.block_on(async {
+        let gate = Arc::new(Gate::default());
+        let encoder = Arc::new(PasswordEncoder::new(
+            Arc::new(GatedHasher(Arc::clone(&gate))),
+            CONCURRENCY,
+            QUEUE_SIZE,
+        ));
+
+        let logins: Vec<JoinHandle<MaaResult<()>>> = (0..LOGINS)
+            .map(|_| {
+                let hash = "gated$v1$password".to_string();
+                tokio::spawn(login(Arc::clone(&encoder), hash))
+            })
+            .collect();
+
+        // 所有哈希名额被占满, 且都阻塞在哈希中; 排不上队的登录请求被立即拒绝
+        let rejected = LOGINS - CONCURRENCY - QUEUE_SIZE;
+        wait_until(|| {
+            gate.running.load(Ordering::SeqCst) == CONCURRENCY
+                && logins.iter().filter(|login| login.is_finished()).count()
+                    == rejected
+        })
+        .await;
+
+        // 哈希占满时其他路由仍然能够立即响应, 超时说明路由被哈希阻塞
+        let response = tokio::time::timeout(
+            DEADLINE,
+            app().oneshot(
+                Request::get("/arknights/level")
+                    .body(Body::empty())
+                    .unwrap(),
+            ),
+        )
+        .await
+        .unwrap()
+        .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        gate.release();
+        let mut ok = 0;
+        let mut busy = 0;
+        for login in logins {
+            match login.await.unwrap() {
+                Ok(()) => ok += 1,
+                Err(MaaError::ServiceBusy) => busy += 1,
+                Err(e) => panic!("unexpected error: {}", e),
+            }
+        }
+        assert_eq!(ok, CONCURRENCY + QUEUE_SIZE);
+        assert_eq!(busy, rejected);
+        // 每个登录都执行了校验和重新哈希
+        assert_eq!(gate.running.load(Ordering::SeqCst), 2 * ok);
+
+        // 所有名额在哈希结束后归还
+        login(Arc::clone(&encoder), "gated$v2$password".to_string())
+            .await
+            .unwrap();
+    });
+}
+
+#[test]
+fn test_saturated_encoder_is_busy() {
+    let runtime = tokio::runtime::Builder::new_multi_thread()
+        .worker_threads(1)
+        .enable_time()
+        .build()
+        .unwrap();
+
+    runtime.block_on(async {
+        let gate = Arc::new(Gate::default());
+        let encoder = Arc::new(PasswordEncoder::new(
+            Arc::new(GatedHasher(Arc::clone(&gate))),
+            1,
+            0,
+        ));
+        let running = {
+            let encoder = Arc::clone(&encoder);
+            tokio::spawn(async move { encoder.encode("password").await })
+        };
+        wait_until(|| gate.running.load(Ordering::SeqCst) == 1).await;
+
+        // 唯一的名额已被占用且不允许排队
+        assert!(matches!(
+            encoder.encode("password").await,
+            Err(MaaError::ServiceBusy)
+        ));
+        gate.release();
+        running.await.unwrap().unwrap();
+    });
+}